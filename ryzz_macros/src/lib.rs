@@ -2,8 +2,9 @@ use proc_macro::TokenStream;
 use proc_macro2::{Span, TokenStream as TokenStream2};
 use quote::{quote, ToTokens};
 use syn::{
-    parse::Parse, parse_macro_input, Attribute, DeriveInput, Error, Expr, ExprAssign, ExprLit,
-    ExprPath, Field, Ident, ItemStruct, Lit, LitStr, PathSegment, Result, Type, TypePath,
+    parse::Parse, parse_macro_input, spanned::Spanned, Attribute, DeriveInput, Error, Expr,
+    ExprAssign, ExprLit, ExprPath, Field, Ident, ItemStruct, Lit, LitStr, PathSegment, Result,
+    Type, TypePath,
 };
 
 #[proc_macro_attribute]
@@ -31,6 +32,8 @@ fn row_macro(mut item_struct: ItemStruct) -> Result<TokenStream2> {
             let attr: Attribute = syn::parse_quote! { #[serde(default)] };
             field.attrs.push(attr);
         }
+        let attrs = serde_attrs(field);
+        field.attrs.extend(attrs);
     }
 
     Ok(quote! {
@@ -39,6 +42,40 @@ fn row_macro(mut item_struct: ItemStruct) -> Result<TokenStream2> {
     })
 }
 
+/// Embeds every `.sql` file in a directory, relative to the crate root, as a
+/// `Vec<ryzz::SqlMigration>` sorted by file name.
+#[proc_macro]
+pub fn embed_migrations(input: TokenStream) -> TokenStream {
+    let dir = parse_macro_input!(input as LitStr);
+    match embed_migrations_macro(dir) {
+        Ok(s) => s.to_token_stream().into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+fn embed_migrations_macro(dir: LitStr) -> Result<TokenStream2> {
+    let root = std::env::var("CARGO_MANIFEST_DIR").unwrap_or_default();
+    let path = std::path::Path::new(&root).join(dir.value());
+    let entries = std::fs::read_dir(&path)
+        .map_err(|e| Error::new(dir.span(), format!("can't read {}: {}", path.display(), e)))?;
+    let mut files = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "sql"))
+        .collect::<Vec<_>>();
+    files.sort();
+    let migrations = files.iter().map(|file| {
+        let name = file
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+        // include_str keeps cargo rebuilding when a file changes
+        let file = file.to_string_lossy().to_string();
+        quote! { ryzz::SqlMigration { name: #name, sql: include_str!(#file) } }
+    });
+
+    Ok(quote! { vec![#(#migrations,)*] })
+}
+
 #[proc_macro_derive(Row, attributes(ryzz))]
 pub fn row_derive(s: TokenStream) -> TokenStream {
     let input = parse_macro_input!(s as DeriveInput);
@@ -99,22 +136,41 @@ fn table_macro(args: Args, mut row_struct: ItemStruct) -> Result<TokenStream2> {
     let table_fields = row_struct
         .fields
         .iter()
+        .filter(|field| !skipped(field))
         .map(|field| {
             let ident = field
                 .ident
                 .as_ref()
                 .ok_or(Error::new(row_ident.span(), "Named fields only"))?;
-            let attrs = &field.attrs;
+            let attrs = field.attrs.iter().filter(|attr| attr.path.is_ident("ryzz"));
+            // #[ryzz(newtype = "i64")] stores a newtype like UserId(i64) as the type it wraps
+            let field_ty = match field
+                .attrs
+                .iter()
+                .filter_map(ryzz_attr)
+                .find_map(|attr| attr.newtype)
+            {
+                Some(inner) => {
+                    let inner = inner.parse::<Type>()?;
+                    match type_col(&field.ty).is_some_and(|col| col.null) {
+                        true => syn::parse_quote! { Option<#inner> },
+                        false => inner,
+                    }
+                }
+                None => field.ty.clone(),
+            };
             // let ty_string = &field.ty.to_token_stream().to_string();
-            let type_col = type_col(&field.ty);
+            let type_col = type_col(&field_ty);
             let vis = &field.vis;
             let span = if let Some(col) = &type_col {
                 col.ident.span()
             } else {
                 ident.span()
             };
-            let ty = match type_col {
-                Some(col) => match (col.null, col.ident.to_string().as_str()) {
+            let ty = match (text_type(&field_ty), type_col) {
+                (Some(false), _) => quote! { ryzz::Text },
+                (Some(true), _) => quote! { ryzz::Null<ryzz::Text> },
+                (None, Some(col)) => match (col.null, col.ident.to_string().as_str()) {
                     (false, "i64") => quote! { ryzz::Integer },
                     (false, "f64") => quote! { ryzz::Real },
                     (false, "Vec<u8>") => quote! { ryzz::Blob },
@@ -130,7 +186,7 @@ fn table_macro(args: Args, mut row_struct: ItemStruct) -> Result<TokenStream2> {
                         ))
                     }
                 },
-                None => {
+                (None, None) => {
                     return Err(Error::new(
                         span,
                         "T must be i64, String, f64, Vec<u8> or Option<T>",
@@ -144,30 +200,82 @@ fn table_macro(args: Args, mut row_struct: ItemStruct) -> Result<TokenStream2> {
 
     let table_alias = match row_attrs
         .iter()
-        .filter_map(|attr| attr.parse_args::<RyzzAttr>().ok())
+        .filter_map(ryzz_attr)
         .find(|attr| attr.r#as.is_some())
     {
         Some(RyzzAttr { r#as, .. }) => quote! { #[ryzz(r#as = #r#as)] },
         None => quote! {},
     };
+    let without_rowid = match row_attrs
+        .iter()
+        .filter_map(ryzz_attr)
+        .any(|attr| attr.without_rowid)
+    {
+        true => quote! { #[ryzz(without_rowid)] },
+        false => quote! {},
+    };
+    let strict = match row_attrs
+        .iter()
+        .filter_map(ryzz_attr)
+        .any(|attr| attr.strict)
+    {
+        true => quote! { #[ryzz(strict)] },
+        false => quote! {},
+    };
+    // forwarded as written, so the derive reports a from without a to and the other way round
+    let foreign_keys = row_attrs
+        .iter()
+        .filter(|attr| ryzz_attr(attr).is_some_and(|attr| attr.from.is_some() || attr.to.is_some()))
+        .collect::<Vec<_>>();
 
-    // strip ryzz attrs from row_struct itself
-    row_struct.attrs = row_attrs
+    // #[ryzz(many = "Child(column)")] on a parent field loads the children whose column matches it
+    let many_impls = row_struct
+        .fields
         .iter()
-        .filter_map(|attr| match attr.parse_args::<RyzzAttr>() {
-            Ok(_) => None,
-            Err(_) => Some(attr.clone()),
+        .flat_map(|field| {
+            field
+                .attrs
+                .iter()
+                .filter_map(ryzz_attr)
+                .filter_map(|attr| attr.many)
+                .map(move |many| (field, many))
         })
-        .collect::<Vec<_>>();
+        .map(|(field, many)| {
+            let value = many.value();
+            let err = || Error::new(many.span(), r#"many should look like "Child(column)""#);
+            let (child, column) = value
+                .strip_suffix(')')
+                .and_then(|value| value.split_once('('))
+                .ok_or_else(err)?;
+            let child = syn::parse_str::<syn::Path>(child.trim()).map_err(|_| err())?;
+            let column = syn::parse_str::<Ident>(column.trim()).map_err(|_| err())?;
+            let ident = &field.ident;
+            Ok(quote! {
+                impl ryzz::HasMany<#child> for #row_ident {
+                    fn many_where(&self) -> ryzz::Sql {
+                        let table = <<#child as ryzz::HasTable>::Table as ryzz::Table>::new();
+                        ryzz::eq(table.#column, self.#ident.clone())
+                    }
+                }
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    // row_attrs, ryzz ones included, go on the row struct once below
+    row_struct.attrs = vec![];
 
     // strip ryzz attrs from row_struct fields
     for field in &mut row_struct.fields {
+        // #[row] turns name and skip into serde attrs, so those survive
         field.attrs = field
             .attrs
             .iter()
             .filter_map(|x| match x.parse_args::<RyzzAttr>() {
-                Ok(_) => None,
-                Err(_) => Some(x.clone()),
+                Ok(attr) if x.path.is_ident("ryzz") && (attr.name.is_some() || attr.skip) => {
+                    Some(x.clone())
+                }
+                Ok(_) if x.path.is_ident("ryzz") => None,
+                _ => Some(x.clone()),
             })
             .collect::<Vec<_>>();
     }
@@ -179,11 +287,20 @@ fn table_macro(args: Args, mut row_struct: ItemStruct) -> Result<TokenStream2> {
 
         #[derive(ryzz::Table, Clone, Copy, Debug, Default)]
         #table_alias
+        #without_rowid
+        #strict
+        #(#foreign_keys)*
         #[ryzz(table = #name)]
         pub struct #table_struct_ident {
             #(#table_fields,)*
         }
 
+        impl ryzz::HasTable for #row_ident {
+            type Table = #table_struct_ident;
+        }
+
+        #(#many_impls)*
+
         impl #row_ident {
             pub async fn table(db: &ryzz::Database) -> core::result::Result<#table_struct_ident, ryzz::Error> {
                 let table = #table_struct_ident::new();
@@ -198,11 +315,12 @@ fn table_macro(args: Args, mut row_struct: ItemStruct) -> Result<TokenStream2> {
                 let pti = ryzz::TableInfoTable::new();
 
                 // add columns
+                // always read the schema from the primary, even when selects go to a replica
                 let db_columns = db
-                    .select(())
+                    .select_primary(())
                     .from(sqlite_schema)
                     .left_outer_join(pti, ryzz::ne(pti.name, sqlite_schema.name))
-                    .where_(ryzz::and(ryzz::eq(sqlite_schema.r#type, "table".to_owned()), ryzz::eq(sqlite_schema.name, table.table_name().to_owned())))
+                    .where_(ryzz::and(ryzz::eq(sqlite_schema.r#type, "table".to_owned()), ryzz::eq(sqlite_schema.name, #name.to_owned())))
                     .all::<ryzz::TableInfoRow>()
                     .await?
                     .into_iter()
@@ -225,9 +343,7 @@ fn table_macro(args: Args, mut row_struct: ItemStruct) -> Result<TokenStream2> {
 
                 if !statements.is_empty() {
                     println!("=== Adding columns ===");
-                    let _ = db
-                        .execute_batch(&format!("BEGIN;{}COMMIT;", statements.join(";")))
-                        .await?;
+                    db.execute_script(&statements.join(";")).await?;
                     for statement in statements {
                         println!("{}", statement);
                     }
@@ -251,15 +367,11 @@ pub fn table_derive(s: TokenStream) -> TokenStream {
 
 fn table_derive_macro(input: DeriveInput) -> Result<TokenStream2> {
     let struct_name = &input.ident;
-    let input_attrs: Vec<RyzzAttr> = input
-        .attrs
-        .iter()
-        .filter_map(|attr| attr.parse_args::<RyzzAttr>().ok())
-        .collect();
+    let input_attrs: Vec<RyzzAttr> = input.attrs.iter().filter_map(ryzz_attr).collect();
     let table_name = match input_attrs
         .iter()
         .filter_map(|attr| attr.table_name.as_ref())
-        .last()
+        .next_back()
     {
         Some(s) => s.value(),
         None => struct_name.to_string(),
@@ -267,24 +379,66 @@ fn table_derive_macro(input: DeriveInput) -> Result<TokenStream2> {
     let table_alias = input_attrs
         .iter()
         .filter_map(|attr| attr.r#as.as_ref())
-        .last();
+        .next_back();
 
+    // table_name() and table_alias() hand back the raw names, only the sql below quotes them
     let (table_name, table_alias) = match table_alias {
         Some(x) => (x.value(), quote! { Some(#table_name) }),
         None => (table_name, quote! { None }),
     };
+    let quoted_table = quote_ident(&table_name);
 
     let fields = ryzz_fields(&input)?;
     let column_names: Vec<_> = fields.iter().map(ryzz_field_name).collect();
     let column_defs = fields.iter().map(column_def).collect::<Vec<_>>();
+    let add_column_arms = fields
+        .iter()
+        .zip(&column_defs)
+        .map(|(f, def)| {
+            let name = ryzz_field_name(f);
+            quote! { #name => #def }
+        })
+        .collect::<Vec<_>>();
+    let column_metas = fields
+        .iter()
+        .filter_map(|f| {
+            let col = type_col(&f.ty)?;
+            let name = ryzz_field_name(f);
+            let sql_type = col.ident.to_string();
+            let primary_key = pk(f).is_some();
+            let not_null = !col.null;
+            let unique = unique(f).is_some();
+            Some(quote! {
+                ryzz::ColumnDef {
+                    name: #name,
+                    sql_type: #sql_type,
+                    primary_key: #primary_key,
+                    not_null: #not_null,
+                    unique: #unique,
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+    let column_arms = fields
+        .iter()
+        .map(|f| {
+            let name = ryzz_field_name(f);
+            let field = f.ident.to_string();
+            let qualified = format!("{}.{}", quoted_table, quote_ident(&name));
+            match name == field {
+                true => quote! { #name => Some(#qualified) },
+                false => quote! { #name | #field => Some(#qualified) },
+            }
+        })
+        .collect::<Vec<_>>();
     let new_fields = fields
         .iter()
         .map(|f| {
             let ident = &f.ident;
-            let name = ryzz_field_name(&f);
-            let value = format!("{}.{}", table_name, name);
+            let name = ryzz_field_name(f);
+            let value = format!("{}.{}", quoted_table, quote_ident(&name));
             let ty = &f.ty;
-            let col = type_col(&ty);
+            let col = type_col(ty);
             Ok(match col {
                 Some(c) => match c.null {
                     true => {
@@ -297,11 +451,68 @@ fn table_derive_macro(input: DeriveInput) -> Result<TokenStream2> {
             })
         })
         .collect::<Result<Vec<_>>>()?;
-    let create_table_sql = format!(
-        "create table if not exists {} ({});",
-        table_name,
-        column_defs.join(",")
-    );
+    let mut table_options = vec![];
+    if input_attrs.iter().any(|attr| attr.without_rowid) {
+        if !fields.iter().any(|f| pk(f).is_some()) {
+            return Err(Error::new(
+                struct_name.span(),
+                "without_rowid tables need a #[ryzz(pk)] column",
+            ));
+        }
+        table_options.push("without rowid");
+    }
+    // #[table] only ever generates Integer, Real, Text and Blob columns, this catches
+    // hand written #[derive(Table)] structs with a column type strict tables don't have
+    if input_attrs.iter().any(|attr| attr.strict) {
+        for field in &fields {
+            let sql_type = type_col(&field.ty)
+                .map(|col| col.ident.to_string())
+                .unwrap_or_default();
+            if !["INT", "INTEGER", "REAL", "TEXT", "BLOB", "ANY"]
+                .contains(&sql_type.to_uppercase().as_str())
+            {
+                return Err(Error::new(
+                    field.ty.span(),
+                    format!(
+                        "strict tables only allow INT, INTEGER, REAL, TEXT, BLOB or ANY columns, not {}",
+                        sql_type
+                    ),
+                ));
+            }
+        }
+        table_options.push("strict");
+    }
+    // #[ryzz(from = "a, b", to = "other(x, y)")] adds a table level, possibly composite, foreign key
+    let mut table_defs = column_defs.clone();
+    for attr in &input_attrs {
+        match (&attr.from, &attr.to) {
+            (Some(from), Some(to)) => table_defs.push(format!(
+                "foreign key ({}) references {}",
+                from.value(),
+                to.value()
+            )),
+            (None, None) => {}
+            (Some(lit), None) | (None, Some(lit)) => {
+                return Err(Error::new(
+                    lit.span(),
+                    "foreign keys need both from and to, e.g. from = \"author_id\", to = \"authors(id)\"",
+                ))
+            }
+        }
+    }
+    let create_table_sql = match table_options.is_empty() {
+        true => format!(
+            "create table if not exists {} ({});",
+            quoted_table,
+            table_defs.join(",")
+        ),
+        false => format!(
+            "create table if not exists {} ({}) {};",
+            quoted_table,
+            table_defs.join(","),
+            table_options.join(", ")
+        ),
+    };
     let struct_string = struct_name.to_string();
     Ok(quote! {
         impl ryzz::Table for #struct_name {
@@ -331,14 +542,24 @@ fn table_derive_macro(input: DeriveInput) -> Result<TokenStream2> {
                 vec![#(#column_names,)*]
             }
 
-            fn add_column_sql(&self, column_name: &str) -> String {
-                let column_defs: Vec<String> = vec![#(#column_defs.to_string(),)*];
-                if let Some(column_def) = column_defs.iter().filter(|c| if let Some(name) = &c.split(" ").nth(0) { if name == &column_name{ true } else { false } } else { false }).nth(0) {
-                    format!("alter table {} add column {};", #table_name, column_def)
-                } else {
-                    panic!("column {} on table {} doesnt exist", column_name, #table_name);
+            fn columns(&self) -> Vec<ryzz::ColumnDef> {
+                vec![#(#column_metas,)*]
+            }
+
+            fn column(&self, name: &str) -> Option<&'static str> {
+                match name {
+                    #(#column_arms,)*
+                    _ => None,
                 }
             }
+
+            fn add_column_sql(&self, column_name: &str) -> String {
+                let column_def = match column_name {
+                    #(#add_column_arms,)*
+                    _ => panic!("column {} on table {} doesnt exist", column_name, #table_name),
+                };
+                format!("alter table {} add column {};", #quoted_table, column_def)
+            }
         }
     })
 }
@@ -350,8 +571,10 @@ impl Parse for RyzzAttr {
             syn::punctuated::Punctuated::<Expr, syn::Token![,]>::parse_terminated(input)?;
         for expr in args_parsed.iter() {
             match expr {
-                Expr::Assign(ExprAssign { left, right, .. }) => match (&**left, &**right) {
-                    (Expr::Path(ExprPath { path, .. }), Expr::Lit(ExprLit { lit, .. })) => {
+                Expr::Assign(ExprAssign { left, right, .. }) => {
+                    if let (Expr::Path(ExprPath { path, .. }), Expr::Lit(ExprLit { lit, .. })) =
+                        (&**left, &**right)
+                    {
                         if let (Some(PathSegment { ident, .. }), Lit::Str(lit_str)) =
                             (path.segments.last(), lit)
                         {
@@ -362,23 +585,37 @@ impl Parse for RyzzAttr {
                                 "r#default" | "default_" => {
                                     ryzz_attr.default_value = Some(lit_str.clone());
                                 }
+                                "default_expr" => {
+                                    ryzz_attr.default_expr = Some(lit_str.clone());
+                                }
                                 "fk" => {
                                     ryzz_attr.references = Some(lit_str.clone());
                                 }
-                                "name" => {
+                                "many" => {
+                                    ryzz_attr.many = Some(lit_str.clone());
+                                }
+                                "from" => {
+                                    ryzz_attr.from = Some(lit_str.clone());
+                                }
+                                "to" => {
+                                    ryzz_attr.to = Some(lit_str.clone());
+                                }
+                                "name" | "column" => {
                                     ryzz_attr.name = Some(lit_str.clone());
                                 }
                                 "r#as" | "as_" => {
                                     ryzz_attr.r#as = Some(lit_str.clone());
                                 }
+                                "newtype" => {
+                                    ryzz_attr.newtype = Some(lit_str.clone());
+                                }
                                 _ => {}
                             }
                         }
                     }
-                    _ => {}
-                },
-                Expr::Path(path) => match path.path.segments.len() {
-                    1 => match path
+                }
+                Expr::Path(path) if path.path.segments.len() == 1 => {
+                    match path
                         .path
                         .segments
                         .first()
@@ -389,10 +626,12 @@ impl Parse for RyzzAttr {
                     {
                         "pk" => ryzz_attr.pk = true,
                         "unique" => ryzz_attr.unique = true,
+                        "skip" => ryzz_attr.skip = true,
+                        "without_rowid" => ryzz_attr.without_rowid = true,
+                        "strict" => ryzz_attr.strict = true,
                         _ => {}
-                    },
-                    _ => {}
-                },
+                    }
+                }
                 _ => {}
             }
         }
@@ -401,7 +640,7 @@ impl Parse for RyzzAttr {
     }
 }
 
-fn type_ident<'a>(ty: &'a Type) -> Option<&'a Ident> {
+fn type_ident(ty: &Type) -> Option<&Ident> {
     match &ty {
         syn::Type::Path(TypePath { path, .. }) => {
             if let Some(seg) = path.segments.last() {
@@ -419,7 +658,31 @@ struct Col<'a> {
     ident: &'a Ident,
 }
 
-fn type_col<'a>(ty: &'a Type) -> Option<Col> {
+// json values, decimals, 128 bit ints and shared strings are stored as text,
+// Some(true) when wrapped in an Option
+fn text_type(ty: &Type) -> Option<bool> {
+    let ty = ty.to_token_stream().to_string().replace(' ', "");
+    let (null, ty) = match ty
+        .strip_prefix("Option<")
+        .and_then(|ty| ty.strip_suffix('>'))
+    {
+        Some(ty) => (true, ty),
+        None => (false, ty.as_str()),
+    };
+    match ty {
+        "serde_json::Value"
+        | "rust_decimal::Decimal"
+        | "i128"
+        | "u128"
+        | "Arc<str>"
+        | "std::sync::Arc<str>"
+        | "Cow<'static,str>"
+        | "std::borrow::Cow<'static,str>" => Some(null),
+        _ => None,
+    }
+}
+
+fn type_col(ty: &Type) -> Option<Col<'_>> {
     match &ty {
         syn::Type::Path(TypePath { path, .. }) => {
             if let Some(seg) = path.segments.last() {
@@ -432,7 +695,7 @@ fn type_col<'a>(ty: &'a Type) -> Option<Col> {
                         Some(arg) => match arg {
                             syn::GenericArgument::Type(ty) => Some(Col {
                                 null: true,
-                                ident: type_ident(&ty)?,
+                                ident: type_ident(ty)?,
                             }),
                             _ => unimplemented!(),
                         },
@@ -453,10 +716,18 @@ struct RyzzAttr {
     table_name: Option<LitStr>,
     pk: bool,
     unique: bool,
+    skip: bool,
+    without_rowid: bool,
+    strict: bool,
     default_value: Option<LitStr>,
+    default_expr: Option<LitStr>,
     references: Option<LitStr>,
+    many: Option<LitStr>,
+    from: Option<LitStr>,
+    to: Option<LitStr>,
     name: Option<LitStr>,
     r#as: Option<LitStr>,
+    newtype: Option<LitStr>,
 }
 
 struct RyzzField {
@@ -481,15 +752,54 @@ fn ryzz_field(field: &Field) -> Result<RyzzField> {
 }
 
 fn ryzz_attr(attr: &Attribute) -> Option<RyzzAttr> {
+    if !attr.path.is_ident("ryzz") {
+        return None;
+    }
     attr.parse_args::<RyzzAttr>().ok()
 }
 
+// keep serde in sync with the columns so values and rows line up
+fn serde_attrs(field: &Field) -> Vec<Attribute> {
+    let attrs = field
+        .attrs
+        .iter()
+        .filter(|attr| attr.path.is_ident("ryzz"))
+        .filter_map(ryzz_attr)
+        .collect::<Vec<_>>();
+    let mut serde_attrs = vec![];
+    if let Some(name) = attrs
+        .iter()
+        .filter_map(|attr| attr.name.as_ref())
+        .next_back()
+    {
+        serde_attrs.push(syn::parse_quote! { #[serde(rename = #name)] });
+    }
+    if attrs.iter().any(|attr| attr.skip) {
+        serde_attrs.push(syn::parse_quote! { #[serde(skip)] });
+    }
+    serde_attrs
+}
+
+fn skipped(field: &Field) -> bool {
+    field
+        .attrs
+        .iter()
+        .filter(|attr| attr.path.is_ident("ryzz"))
+        .filter_map(ryzz_attr)
+        .any(|attr| attr.skip)
+}
+
+// same as ryzz's quote_ident, every identifier in the generated sql is double quoted
+fn quote_ident(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
 fn ryzz_field_name(field: &RyzzField) -> String {
     match field
         .attrs
         .iter()
         .filter_map(|attr| attr.name.as_ref())
-        .last()
+        .next_back()
     {
         Some(name) => name.value(),
         None => field.ident.to_string(),
@@ -498,13 +808,18 @@ fn ryzz_field_name(field: &RyzzField) -> String {
 
 fn ryzz_fields(input: &DeriveInput) -> Result<Vec<RyzzField>> {
     match &input.data {
-        syn::Data::Struct(ds) => ds.fields.iter().map(ryzz_field).collect(),
+        syn::Data::Struct(ds) => ds
+            .fields
+            .iter()
+            .filter(|field| !skipped(field))
+            .map(ryzz_field)
+            .collect(),
         _ => unimplemented!(),
     }
 }
 
 fn column_def(field: &RyzzField) -> String {
-    let name = ryzz_field_name(&field);
+    let name = quote_ident(&ryzz_field_name(field));
     let Some(type_col) = type_col(&field.ty) else {
         return "".into();
     };
@@ -517,49 +832,97 @@ fn column_def(field: &RyzzField) -> String {
         },
         pk(field),
         unique(field),
-        r#default(field),
+        r#default(field, &type_col.ident.to_string()),
         fk(field),
     ]
     .into_iter()
-    .filter_map(|s| s)
+    .flatten()
     .collect::<Vec<_>>()
     .join(" ")
 }
 
 fn pk(field: &RyzzField) -> Option<String> {
-    match field.attrs.iter().find(|attr| attr.pk == true) {
-        Some(_) => Some("primary key".into()),
-        None => None,
-    }
+    field
+        .attrs
+        .iter()
+        .find(|attr| attr.pk)
+        .map(|_| "primary key".into())
 }
 
 fn unique(field: &RyzzField) -> Option<String> {
-    match field.attrs.iter().find(|attr| attr.unique == true) {
-        Some(_) => Some("unique".into()),
-        None => None,
-    }
+    field
+        .attrs
+        .iter()
+        .find(|attr| attr.unique)
+        .map(|_| "unique".into())
 }
 
-fn r#default(field: &RyzzField) -> Option<String> {
-    match field
+fn r#default(field: &RyzzField, sql_type: &str) -> Option<String> {
+    if let Some(expr) = field
         .attrs
         .iter()
-        .filter_map(|attr| attr.default_value.as_ref())
-        .last()
+        .filter_map(|attr| attr.default_expr.as_ref())
+        .next_back()
     {
-        Some(r#default) => Some(format!("default {}", r#default.value())),
-        None => None,
+        return Some(default_expr(&expr.value()));
+    }
+    field
+        .attrs
+        .iter()
+        .filter_map(|attr| attr.default_value.as_ref())
+        .next_back()
+        .map(|r#default| format!("default {}", default_literal(&r#default.value(), sql_type)))
+}
+
+// bare text defaults like `active` are quoted, quoted strings, keywords and
+// expressions like `(datetime('now'))` are passed through as written
+fn default_literal(value: &str, sql_type: &str) -> String {
+    let keyword = matches!(
+        value.to_lowercase().as_str(),
+        "null" | "current_timestamp" | "current_date" | "current_time"
+    );
+    let expression = value.starts_with('\'') || parenthesized(value);
+    if sql_type == "Text" && !keyword && !expression {
+        format!("'{}'", value.replace('\'', "''"))
+    } else {
+        value.to_string()
+    }
+}
+
+// whether the first ( closes on the last char, so `(a) (b)` isn't one expression
+fn parenthesized(value: &str) -> bool {
+    if !value.starts_with('(') {
+        return false;
+    }
+    let mut depth = 0;
+    for (i, c) in value.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            _ => {}
+        }
+        if depth == 0 {
+            return i == value.len() - 1;
+        }
+    }
+    false
+}
+
+// sqlite only accepts literals and these keywords bare, anything else needs parens
+fn default_expr(expr: &str) -> String {
+    match expr.to_lowercase().as_str() {
+        "current_timestamp" | "current_date" | "current_time" | "null" | "true" | "false" => {
+            format!("default {}", expr)
+        }
+        _ => format!("default ({})", expr),
     }
 }
 
 fn fk(field: &RyzzField) -> Option<String> {
-    match field
+    field
         .attrs
         .iter()
         .filter_map(|attr| attr.references.as_ref())
-        .last()
-    {
-        Some(fk) => Some(format!("references {}", fk.value())),
-        None => None,
-    }
+        .next_back()
+        .map(|fk| format!("references {}", fk.value()))
 }
@@ -5,10 +5,21 @@ pub use rusqlite;
 pub use rusqlite::types::Value;
 pub use rusqlite::ToSql;
 use rusqlite::{params_from_iter, OpenFlags};
-pub use ryzz_macros::{row, table, Row, Table};
+pub use ryzz_macros::{embed_migrations, row, table, Row, Table};
 use serde::{de::DeserializeOwned, Serialize};
 use serde_rusqlite::NamedParamSlice;
-use std::{fmt::Display, sync::Arc};
+use std::{
+    borrow::Cow,
+    collections::BTreeMap,
+    fmt::Display,
+    future::Future,
+    io,
+    pin::Pin,
+    sync::{Arc, OnceLock},
+    task::{Context, Poll, Waker},
+    time::Duration,
+};
+use tokio::io::{AsyncRead, ReadBuf};
 pub use tokio_rusqlite;
 
 #[derive(Clone, Debug)]
@@ -16,6 +27,9 @@ pub struct Connection {
     path: Arc<str>,
     open_flags: OpenFlags,
     pragma: Option<String>,
+    statement_cache_capacity: Option<usize>,
+    journal_mode: Option<JournalMode>,
+    busy_handler: Option<BusyHandler>,
 }
 
 impl Connection {
@@ -24,6 +38,9 @@ impl Connection {
             path: path.into(),
             open_flags: OpenFlags::default(),
             pragma: None,
+            statement_cache_capacity: None,
+            journal_mode: None,
+            busy_handler: None,
         }
     }
 
@@ -42,7 +59,7 @@ impl Connection {
     }
 
     pub fn read_only(mut self, arg: bool) -> Self {
-        if arg == true {
+        if arg {
             self.open_flags = self.open_flags.union(OpenFlags::SQLITE_OPEN_READ_ONLY);
         }
         self
@@ -62,6 +79,7 @@ impl Connection {
     }
 
     pub fn journal_mode(mut self, mode: JournalMode) -> Self {
+        self.journal_mode = Some(mode);
         let value = match mode {
             JournalMode::Delete => "DELETE",
             JournalMode::Truncate => "TRUNCATE",
@@ -114,17 +132,89 @@ impl Connection {
         self
     }
 
+    /// Lets readers see uncommitted writes, this only matters with a shared cache.
+    pub fn read_uncommitted(self, on: bool) -> Self {
+        let val = match on {
+            true => "ON",
+            false => "OFF",
+        };
+        self.pragma(&format!("read_uncommitted = {}", val))
+    }
+
+    /// Sets how many prepared statements rusqlite keeps around, the default is 16.
+    pub fn statement_cache_capacity(mut self, capacity: usize) -> Self {
+        self.statement_cache_capacity = Some(capacity);
+        self
+    }
+
+    /// Called with the retry count each time the database is busy, return false to give up
+    /// with `SQLITE_BUSY`. Replaces any `busy_timeout` pragma.
+    pub fn busy_handler(mut self, f: impl Fn(i32) -> bool + Send + Sync + 'static) -> Self {
+        self.busy_handler = Some(BusyHandler(Arc::new(f)));
+        self
+    }
+
+    // catches settings that can't work together before sqlite fails on them less clearly
+    fn validate(&self) -> Result<(), Error> {
+        let read_only = self.open_flags.contains(OpenFlags::SQLITE_OPEN_READ_ONLY);
+        if read_only
+            && self.journal_mode == Some(JournalMode::Wal)
+            && !std::path::Path::new(self.path.as_ref()).exists()
+        {
+            return Err(Error::Config(format!(
+                "journal_mode = WAL can't be set on {}, it doesn't exist and the connection is read only",
+                self.path
+            )));
+        }
+        Ok(())
+    }
+
     pub async fn open(&self) -> Result<tokio_rusqlite::Connection, Error> {
+        self.validate()?;
         let conn = tokio_rusqlite::Connection::open_with_flags(self.path.as_ref(), self.open_flags)
             .await?;
         if let Some(p) = self.pragma.clone() {
-            let _ = conn.call(move |conn| conn.execute_batch(&p)).await?;
+            conn.call(move |conn| conn.execute_batch(&p)).await?;
+        }
+        if let Some(capacity) = self.statement_cache_capacity {
+            conn.call(move |conn| {
+                conn.set_prepared_statement_cache_capacity(capacity);
+                Ok(())
+            })
+            .await?;
+        }
+        // after the pragmas, a busy_timeout pragma would clear the handler
+        if let Some(handler) = self.busy_handler.clone() {
+            conn.call(move |conn| {
+                BUSY_HANDLER.set(Some(handler));
+                conn.busy_handler(Some(call_busy_handler))
+            })
+            .await?;
         }
 
         Ok(conn)
     }
 }
 
+#[derive(Clone)]
+struct BusyHandler(Arc<dyn Fn(i32) -> bool + Send + Sync>);
+
+impl std::fmt::Debug for BusyHandler {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("BusyHandler")
+    }
+}
+
+// rusqlite only takes a fn pointer for the busy handler. every tokio_rusqlite connection
+// runs on a thread of its own, so the closure lives in that thread and a fn calls it
+thread_local! {
+    static BUSY_HANDLER: std::cell::RefCell<Option<BusyHandler>> = Default::default();
+}
+
+fn call_busy_handler(count: i32) -> bool {
+    BUSY_HANDLER.with_borrow(|handler| handler.as_ref().is_some_and(|handler| (handler.0)(count)))
+}
+
 #[table("sqlite_schema")]
 pub struct SqliteSchema {
     pub name: String,
@@ -170,8 +260,32 @@ impl TableInfoRow {
 }
 
 pub trait Select {
-    fn columns(&self) -> Vec<&'static str>;
+    fn columns(&self) -> Vec<Cow<'static, str>>;
     fn clause(&self) -> SelectClause;
+
+    fn column_list(&self) -> String {
+        self.columns().join(", ")
+    }
+}
+
+/// Builds an `Sql` from raw sql and its binds, run it with `Database::query` or `Database::execute_sql`.
+/// Every argument binds one `?`, `Value::Null` included.
+#[macro_export]
+macro_rules! sql {
+    ($clause:expr $(, $param:expr)* $(,)?) => {
+        $crate::Sql {
+            clause: $clause.to_string(),
+            params: vec![$($crate::ToValueColumn::to_value(&$param).unwrap_or($crate::Value::Null),)*],
+        }
+    };
+}
+
+/// Groups columns into a tuple for `select`, e.g. `db.select(columns!(accounts.id, accounts.name))`.
+#[macro_export]
+macro_rules! columns {
+    ($($col:expr),+ $(,)?) => {
+        ($($col,)+)
+    };
 }
 
 macro_rules! impl_select {
@@ -181,7 +295,7 @@ macro_rules! impl_select {
             where
                 #(T~N: ToColumn,)*
             {
-                fn columns(&self) -> Vec<&'static str> {
+                fn columns(&self) -> Vec<Cow<'static, str>> {
                     vec![#(self.N.to_column(),)*]
                 }
 
@@ -203,7 +317,7 @@ seq_macro::seq!(N in 0..16 {
 });
 
 impl Select for () {
-    fn columns(&self) -> Vec<&'static str> {
+    fn columns(&self) -> Vec<Cow<'static, str>> {
         vec![]
     }
 
@@ -216,7 +330,7 @@ impl<A> Select for A
 where
     A: ToColumn,
 {
-    fn columns(&self) -> Vec<&'static str> {
+    fn columns(&self) -> Vec<Cow<'static, str>> {
         vec![self.to_column()]
     }
 
@@ -230,16 +344,66 @@ where
     }
 }
 
+// the column part of a `"table"."column"` name, with its quotes taken off
 fn unqualify(s: &str) -> String {
-    s.split(".").nth(1).unwrap_or(s).to_string()
+    let mut parts = vec![String::new()];
+    let mut quoted = false;
+    let mut chars = s.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                chars.next();
+                parts.last_mut().unwrap().push('"');
+            }
+            '"' => quoted = !quoted,
+            '.' if !quoted => parts.push(String::new()),
+            c => parts.last_mut().unwrap().push(c),
+        }
+    }
+    parts.pop().unwrap_or_default()
+}
+
+// every identifier goes into the sql double quoted, so keywords like order and
+// names with spaces or quotes in them all work the same way
+fn quote_ident(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+// table valued functions like pragma_table_info(...) are sql, not names, so they go in as written
+fn table_source(name: &str) -> std::borrow::Cow<'_, str> {
+    match name.contains('(') {
+        true => name.into(),
+        false => quote_ident(name).into(),
+    }
 }
 
+#[allow(unused)]
 #[table("ryzz_migrations")]
 struct Migration {
     #[ryzz(unique)]
+    name: String,
     sql: String,
 }
 
+/// A raw sql migration, usually from `embed_migrations!("migrations")`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SqlMigration {
+    pub name: &'static str,
+    pub sql: &'static str,
+}
+
+/// When a transaction takes its locks, see `Database::transaction_with`.
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransactionBehavior {
+    /// No locks until the first read or write.
+    #[default]
+    Deferred,
+    /// The write lock up front, other writers wait or fail with busy.
+    Immediate,
+    /// The write lock up front, outside of WAL mode readers are locked out too.
+    Exclusive,
+}
+
 #[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
 pub enum JournalMode {
     Delete,
@@ -260,12 +424,185 @@ pub enum Synchronous {
     Extra,
 }
 
-async fn execute(connection: &tokio_rusqlite::Connection, sql: Sql) -> Result<usize, Error> {
-    let params = params_from_iter(sql.params.into_iter());
+#[derive(Clone, Debug, Default)]
+struct QueryOptions {
+    timeout: Option<Duration>,
+    attempts: usize,
+    on_query: Option<QueryHook>,
+    cache: bool,
+    // whether sqlite takes order by and limit on delete and update, looked up once per database
+    update_delete_limit: Arc<OnceLock<bool>>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum QueryKind {
+    Select,
+    Insert,
+    Update,
+    Delete,
+    Other,
+}
+
+impl QueryKind {
+    fn from_sql(sql: &str) -> Self {
+        match sql
+            .split_whitespace()
+            .next()
+            .map(|s| s.to_lowercase())
+            .as_deref()
+        {
+            Some("select") => QueryKind::Select,
+            Some("insert") => QueryKind::Insert,
+            Some("update") => QueryKind::Update,
+            Some("delete") => QueryKind::Delete,
+            _ => QueryKind::Other,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct QueryEvent {
+    pub sql: Arc<str>,
+    pub duration: Duration,
+    pub rows: usize,
+    pub kind: QueryKind,
+}
+
+#[derive(Clone)]
+struct QueryHook(Arc<dyn Fn(QueryEvent) + Send + Sync>);
+
+impl std::fmt::Debug for QueryHook {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("QueryHook")
+    }
+}
+
+// times f and reports it to the on_query hook when there is one
+async fn observed<T, Fut>(
+    options: &QueryOptions,
+    sql: &str,
+    rows: impl Fn(&T) -> usize,
+    f: Fut,
+) -> Result<T, Error>
+where
+    Fut: Future<Output = Result<T, Error>>,
+{
+    let Some(hook) = &options.on_query else {
+        return f.await;
+    };
+    let start = std::time::Instant::now();
+    let result = f.await?;
+    (hook.0)(QueryEvent {
+        sql: sql.into(),
+        duration: start.elapsed(),
+        rows: rows(&result),
+        kind: QueryKind::from_sql(sql),
+    });
+    Ok(result)
+}
+
+// interrupts f if it takes longer than the timeout. sqlite polls the progress handler
+// while f's statements run and it's removed after, so the interrupt can't land on the
+// next statement on this connection
+fn with_timeout<T>(
+    conn: &rusqlite::Connection,
+    timeout: Option<Duration>,
+    f: impl FnOnce(&rusqlite::Connection) -> rusqlite::Result<T>,
+) -> rusqlite::Result<T> {
+    let Some(timeout) = timeout else {
+        return f(conn);
+    };
+    let deadline = std::time::Instant::now() + timeout;
+    conn.progress_handler(1000, Some(move || std::time::Instant::now() >= deadline));
+    let result = f(conn);
+    conn.progress_handler(0, None::<fn() -> bool>);
+    result
+}
+
+fn call_error(err: tokio_rusqlite::Error) -> Error {
+    match err {
+        tokio_rusqlite::Error::Rusqlite(rusqlite::Error::SqliteFailure(e, _))
+            if e.code == rusqlite::ErrorCode::OperationInterrupted =>
+        {
+            Error::Interrupted
+        }
+        err => err.into(),
+    }
+}
+
+fn is_busy(err: &Error) -> bool {
+    // the primary result code is the low byte of the extended one
+    matches!(
+        err,
+        Error::Sqlite { code, .. }
+            if matches!(code & 0xff, rusqlite::ffi::SQLITE_BUSY | rusqlite::ffi::SQLITE_LOCKED)
+    )
+}
+
+// reruns f on busy or locked errors, doubling the wait each time
+async fn retrying<T, Fut>(options: &QueryOptions, mut f: impl FnMut() -> Fut) -> Result<T, Error>
+where
+    Fut: Future<Output = Result<T, Error>>,
+{
+    let mut attempt = 1;
+    let mut backoff = Duration::from_millis(10);
+    loop {
+        match f().await {
+            Err(err) if attempt < options.attempts && is_busy(&err) => {
+                tokio::time::sleep(backoff).await;
+                attempt += 1;
+                backoff *= 2;
+            }
+            result => return result,
+        }
+    }
+}
+
+// statements are prepared fresh unless the query opted into rusqlite's statement cache
+fn with_statement<T>(
+    conn: &rusqlite::Connection,
+    clause: &str,
+    cache: bool,
+    f: impl FnOnce(&mut rusqlite::Statement) -> rusqlite::Result<T>,
+) -> rusqlite::Result<T> {
+    match cache {
+        true => f(&mut *conn.prepare_cached(clause)?),
+        false => f(&mut conn.prepare(clause)?),
+    }
+}
+
+async fn execute_with(
+    connection: &tokio_rusqlite::Connection,
+    sql: Sql,
+    options: &QueryOptions,
+) -> Result<usize, Error> {
+    let clause = sql.clause.clone();
+    observed(
+        options,
+        &clause,
+        |affected| *affected,
+        retrying(options, || execute_once(connection, sql.clone(), options)),
+    )
+    .await
+}
+
+async fn execute_once(
+    connection: &tokio_rusqlite::Connection,
+    sql: Sql,
+    options: &QueryOptions,
+) -> Result<usize, Error> {
+    let params = params_from_iter(sql.params);
     let clause = sql.clause;
+    let timeout = options.timeout;
+    let cache = options.cache;
     let results = connection
-        .call(move |conn| conn.prepare(&clause)?.execute(params))
-        .await?;
+        .call(move |conn| {
+            with_timeout(conn, timeout, |conn| {
+                with_statement(conn, &clause, cache, |stmt| stmt.execute(params))
+            })
+        })
+        .await
+        .map_err(call_error)?;
 
     Ok(results)
 }
@@ -274,20 +611,49 @@ pub async fn rows<T: DeserializeOwned + Send + 'static>(
     connection: &tokio_rusqlite::Connection,
     sql: Sql,
 ) -> Result<Vec<T>, Error> {
-    let params = params_from_iter(sql.params.into_iter());
+    rows_with(connection, sql, &QueryOptions::default()).await
+}
+
+async fn rows_with<T: DeserializeOwned + Send + 'static>(
+    connection: &tokio_rusqlite::Connection,
+    sql: Sql,
+    options: &QueryOptions,
+) -> Result<Vec<T>, Error> {
+    let clause = sql.clause.clone();
+    observed(
+        options,
+        &clause,
+        |rows| rows.len(),
+        retrying(options, || rows_once(connection, sql.clone(), options)),
+    )
+    .await
+}
+
+async fn rows_once<T: DeserializeOwned + Send + 'static>(
+    connection: &tokio_rusqlite::Connection,
+    sql: Sql,
+    options: &QueryOptions,
+) -> Result<Vec<T>, Error> {
+    let params = params_from_iter(sql.params);
     let clause = sql.clause;
+    let timeout = options.timeout;
+    let cache = options.cache;
     let results = connection
         .call(move |conn| {
-            let mut stmt = conn.prepare(&clause)?;
-            let rows = stmt.query(params)?;
-            // HACK there is only ever one column, it should always be valid json
-            rows.mapped(|row| {
-                let json: String = row.get(0)?;
-                Ok(json)
+            with_timeout(conn, timeout, |conn| {
+                with_statement(conn, &clause, cache, |stmt| {
+                    let rows = stmt.query(params)?;
+                    // HACK there is only ever one column, it should always be valid json
+                    rows.mapped(|row| {
+                        let json: String = row.get(0)?;
+                        Ok(json)
+                    })
+                    .collect::<Result<Vec<_>, rusqlite::Error>>()
+                })
             })
-            .collect::<Result<Vec<_>, rusqlite::Error>>()
         })
-        .await?;
+        .await
+        .map_err(call_error)?;
 
     results
         .into_iter()
@@ -295,38 +661,83 @@ pub async fn rows<T: DeserializeOwned + Send + 'static>(
         .collect::<Result<Vec<T>, Error>>()
 }
 
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Order {
+    #[default]
+    Asc,
+    Desc,
+}
+
+impl Display for Order {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Order::Asc => "asc",
+            Order::Desc => "desc",
+        })
+    }
+}
+
+/// Sorts by a direction picked at runtime, only the column object and enum end up in the sql.
+pub fn order_by_col(col: impl ToColumn, order: Order) -> Sql {
+    Sql {
+        clause: format!("{} {}", col.to_column(), order),
+        params: vec![],
+    }
+}
+
 pub fn asc(col: impl ToColumn) -> Sql {
     Sql {
-        clause: format!("{} asc", col.to_column()).into(),
+        clause: format!("{} asc", col.to_column()),
         params: vec![],
     }
 }
 
 pub fn desc(col: impl ToColumn) -> Sql {
     Sql {
-        clause: format!("{} desc", col.to_column()).into(),
+        clause: format!("{} desc", col.to_column()),
         params: vec![],
     }
 }
 
 #[derive(Clone, Debug)]
-struct Tbl<'a> {
-    table_name: Option<&'a str>,
-    column_names: Vec<&'static str>,
+struct Tbl {
+    table_name: Option<Cow<'static, str>>,
+    column_names: Vec<Cow<'static, str>>,
+}
+
+impl Tbl {
+    fn new(table: &dyn Table) -> Self {
+        Self {
+            table_name: Some(table.table_name().into()),
+            column_names: table.column_names().into_iter().map(Cow::from).collect(),
+        }
+    }
 }
 
 fn column_name(table_name: Option<&str>, column_name: &str) -> String {
     match table_name {
-        Some(t) => format!("{}.{}", t, column_name),
-        None => format!("{}", column_name),
+        Some(t) => format!("{}.{}", quote_ident(t), quote_ident(column_name)),
+        None => column_name.to_string(),
+    }
+}
+
+// joins clause fragments with single spaces without collecting them first
+fn push_part(sql: &mut String, part: &str) {
+    if !sql.is_empty() {
+        sql.push(' ');
     }
+    sql.push_str(part);
 }
 
 fn json_object(tbl: &Tbl, r#as: bool) -> String {
-    let r#as = match tbl.table_name {
+    json_object_with(tbl, r#as, &[])
+}
+
+fn json_object_with(tbl: &Tbl, r#as: bool, extra: &[String]) -> String {
+    let r#as = match &tbl.table_name {
         Some(s) => {
             if r#as {
-                format!("as {}", s)
+                format!("as {}", quote_ident(s))
             } else {
                 "".into()
             }
@@ -339,9 +750,14 @@ fn json_object(tbl: &Tbl, r#as: bool) -> String {
             .iter()
             .map(|col| {
                 // HACK Stop qualifying column names in proc macro
-                let c = unqualify(&col);
-                format!(r#"'{}', {}"#, c, column_name(tbl.table_name, col))
+                let c = unqualify(col);
+                format!(
+                    r#"'{}', {}"#,
+                    c.replace('\'', "''"),
+                    column_name(tbl.table_name.as_deref(), col)
+                )
             })
+            .chain(extra.iter().cloned())
             .collect::<Vec<_>>()
             .join(","),
         r#as
@@ -355,6 +771,27 @@ pub enum SelectClause {
     None,
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OnConflict {
+    Replace,
+    Ignore,
+    Abort,
+    Fail,
+    Rollback,
+}
+
+impl Display for OnConflict {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            OnConflict::Replace => "replace",
+            OnConflict::Ignore => "ignore",
+            OnConflict::Abort => "abort",
+            OnConflict::Fail => "fail",
+            OnConflict::Rollback => "rollback",
+        })
+    }
+}
+
 pub enum JoinType {
     Left,
     Right,
@@ -375,36 +812,52 @@ impl Display for JoinType {
     }
 }
 
+#[derive(Clone)]
 pub struct Query<'a> {
     connection: &'a tokio_rusqlite::Connection,
     select: SelectClause,
-    from: Option<Tbl<'a>>,
+    select_columns: Vec<Cow<'static, str>>,
+    select_exprs: Vec<SelectExpr>,
+    from: Option<Tbl>,
+    from_sql: Option<Arc<str>>,
+    on_conflict: Option<Arc<str>>,
     r#where: Option<Arc<str>>,
     limit: Option<Arc<str>>,
+    offset: Option<Arc<str>>,
     insert_into: Option<Arc<str>>,
     set: Option<Arc<str>>,
     delete: Option<Arc<str>>,
     values_sql: Option<Arc<str>>,
     returning: Option<Arc<str>>,
     values: Vec<Value>,
+    limit_values: Vec<Value>,
+    set_values: Vec<Value>,
     update: Option<Arc<str>>,
     order: Option<Arc<str>>,
     group_by: Option<Arc<str>>,
     joins: Option<String>,
-    tables: Vec<Tbl<'a>>,
+    tables: Vec<Tbl>,
     default_values: Option<Arc<str>>,
+    options: QueryOptions,
 }
 
 impl<'a> Query<'a> {
     pub fn new(connection: &'a tokio_rusqlite::Connection) -> Self {
         Self {
             select: SelectClause::None,
+            select_columns: vec![],
+            select_exprs: vec![],
             from: None,
+            from_sql: None,
+            on_conflict: None,
             r#where: None,
             limit: None,
+            offset: None,
             insert_into: None,
             values_sql: None,
             values: vec![],
+            limit_values: vec![],
+            set_values: vec![],
             delete: None,
             set: None,
             update: None,
@@ -414,25 +867,65 @@ impl<'a> Query<'a> {
             joins: None,
             tables: vec![],
             default_values: None,
+            options: QueryOptions::default(),
             connection,
         }
     }
 
     pub fn select(mut self, columns: impl Select) -> Self {
         self.select = columns.clause();
+        self.select_columns = columns.columns();
+        self
+    }
+
+    /// Adds a computed column to the select list. Its binds come before any where binds.
+    pub fn select_expr(mut self, expr: SelectExpr) -> Self {
+        self.select_exprs.push(expr);
         self
     }
 
     pub fn from(mut self, table: impl Table) -> Self {
+        self.from = Some(Tbl::new(&table));
+        self.from_sql = Some(fragment(format!(
+            "from {}",
+            quote_ident(table.table_name())
+        )));
+        self.tables.push(Tbl::new(&table));
+        self
+    }
+
+    /// `from table as alias`, reference its columns with `aliased(alias, column)`.
+    pub fn from_as(mut self, table: impl Table, alias: &str) -> Self {
         let tbl = Tbl {
-            table_name: Some(table.table_name()),
-            column_names: table.column_names(),
+            table_name: Some(alias.to_string().into()),
+            ..Tbl::new(&table)
         };
-        self.from = Some(tbl);
-        self.tables.push(Tbl {
-            table_name: Some(table.table_name()),
-            column_names: table.column_names(),
-        });
+        self.from_sql = Some(fragment(format!(
+            "from {} as {}",
+            table_source(table.table_alias().unwrap_or(table.table_name())),
+            quote_ident(alias)
+        )));
+        self.from = Some(tbl.clone());
+        self.tables.push(tbl);
+        self
+    }
+
+    /// Selects from several tables at once, `from a, b`, leaving the join condition to `where_`.
+    pub fn from_tables(mut self, tables: Vec<&dyn Table>) -> Self {
+        let names = tables
+            .iter()
+            .map(|table| quote_ident(table.table_name()))
+            .collect::<Vec<_>>();
+        for (i, table) in tables.into_iter().enumerate() {
+            let tbl = Tbl::new(table);
+            if i == 0 {
+                self.from = Some(tbl.clone());
+            }
+            self.tables.push(tbl);
+        }
+        if !names.is_empty() {
+            self.from_sql = Some(fragment(format!("from {}", names.join(", "))));
+        }
         self
     }
 
@@ -443,7 +936,7 @@ impl<'a> Query<'a> {
             .collect::<Vec<_>>()
             .join(",");
         self.order = match self.order {
-            Some(order) => Some(format!("{} {}", column_names, order).into()),
+            Some(order) => Some(format!("{},{}", order, column_names).into()),
             None => Some(format!("order by {}", column_names).into()),
         };
 
@@ -452,7 +945,7 @@ impl<'a> Query<'a> {
 
     #[deprecated(since = "0.1.0", note = "please use `where_` instead")]
     pub fn r#where(mut self, sql: Sql) -> Self {
-        if let None = self.r#where {
+        if self.r#where.is_none() {
             self.r#where = Some(format!("where {}", sql.clause).into())
         }
         self.values.extend(sql.params);
@@ -460,42 +953,81 @@ impl<'a> Query<'a> {
     }
 
     pub fn where_(mut self, sql: Sql) -> Self {
-        if let None = self.r#where {
+        if self.r#where.is_none() {
             self.r#where = Some(format!("where {}", sql.clause).into())
         }
         self.values.extend(sql.params);
         self
     }
 
+    /// Builds the where clause in `f`, every pushed predicate is anded together.
+    pub fn where_with(self, f: impl FnOnce(&mut WhereBuilder)) -> Self {
+        let mut builder = WhereBuilder::default();
+        f(&mut builder);
+        match builder.predicates.into_iter().reduce(and) {
+            Some(sql) => self.where_(sql),
+            None => self,
+        }
+    }
+
     pub fn join(mut self, join_type: JoinType, outer: bool, table: impl Table, sql: Sql) -> Self {
         let clause = format!(
             "{} {} join {} {} on {}",
             join_type,
             if outer { "outer" } else { "" },
-            if let Some(alias) = table.table_alias() {
-                alias
-            } else {
-                table.table_name()
+            match table.table_alias() {
+                Some(source) => table_source(source),
+                None => quote_ident(table.table_name()).into(),
             },
-            if let Some(_) = table.table_alias() {
-                table.table_name()
-            } else {
-                ""
+            match table.table_alias() {
+                Some(_) => quote_ident(table.table_name()),
+                None => "".into(),
             },
             sql.clause
         );
         match self.joins {
             Some(ref mut joins) => joins.push_str(&clause),
-            None => self.joins = Some(clause.into()),
+            None => self.joins = Some(clause),
+        }
+        self.tables.push(Tbl::new(&table));
+
+        self
+    }
+
+    /// Joins `table` under `alias`, which lets a table join to itself.
+    pub fn join_as(
+        mut self,
+        join_type: JoinType,
+        outer: bool,
+        table: impl Table,
+        alias: &str,
+        sql: Sql,
+    ) -> Self {
+        let clause = format!(
+            " {} {} join {} as {} on {}",
+            join_type,
+            if outer { "outer" } else { "" },
+            table_source(table.table_alias().unwrap_or(table.table_name())),
+            quote_ident(alias),
+            sql.clause
+        );
+        match self.joins {
+            Some(ref mut joins) => joins.push_str(&clause),
+            None => self.joins = Some(clause.trim_start().to_string()),
         }
+        self.values.extend(sql.params);
         self.tables.push(Tbl {
-            table_name: Some(table.table_name()),
-            column_names: table.column_names(),
+            table_name: Some(alias.to_string().into()),
+            ..Tbl::new(&table)
         });
 
         self
     }
 
+    pub fn inner_join_as(self, table: impl Table, alias: &str, sql: Sql) -> Self {
+        self.join_as(JoinType::Inner, false, table, alias, sql)
+    }
+
     pub fn inner_join(self, table: impl Table, sql: Sql) -> Self {
         self.join(JoinType::Inner, false, table, sql)
     }
@@ -514,100 +1046,255 @@ impl<'a> Query<'a> {
         self
     }
 
+    /// Selects `group_cols` plus `aggregates` and groups by `group_cols`, the usual report shape.
+    /// With no `group_cols` it selects just the aggregates over every row.
+    pub fn aggregate(mut self, group_cols: impl Select, aggregates: Vec<SelectExpr>) -> Self {
+        let columns = group_cols.columns();
+        if columns.is_empty() {
+            self.select = SelectClause::None;
+            self.select_columns.clear();
+            self.group_by = None;
+        } else {
+            self = self.select(group_cols);
+            self.group_by = Some(format!("group by {}", columns.join(",")).into());
+        }
+        self.select_exprs.extend(aggregates);
+        self
+    }
+
+    /// One row per distinct `columns`, emulated with `group by` since sqlite has no `distinct on`.
+    /// Which row of a group is returned is up to sqlite unless the select uses `min` or `max`.
+    pub fn distinct_on(mut self, columns: impl Select) -> Self {
+        self.group_by = Some(format!("group by {}", columns.columns().join(",")).into());
+        self
+    }
+
     pub fn limit(mut self, limit: i64) -> Self {
         self.limit = Some(format!("limit {}", limit).into());
+        self.limit_values.clear();
+        self
+    }
+
+    /// Skips the first `offset` rows, sqlite only accepts it after a `limit`.
+    pub fn offset(mut self, offset: i64) -> Self {
+        self.offset = Some(format!("offset {}", offset).into());
+        self
+    }
+
+    /// Like `limit` but binds the value, so one prepared statement serves any limit.
+    pub fn limit_param(mut self, limit: i64) -> Self {
+        self.limit = Some(fragment("limit ?"));
+        self.limit_values = vec![Value::Integer(limit)];
+        self
+    }
+
+    /// Interrupts the query with `Error::Interrupted` once it runs longer than `timeout`.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.options.timeout = Some(timeout);
+        self
+    }
+
+    /// Copies the from, joins and where of this query into a `select count(*)`,
+    /// dropping the select list, order, limit and offset. Read it back as a `RowCount` or `Count`.
+    /// A grouped query, `distinct_on` included, counts its groups with
+    /// `select count(*) from (<query>)` instead of the rows going into them.
+    pub fn clone_for_count(&self) -> Self {
+        let mut query = self.clone();
+        query.order = None;
+        query.limit = None;
+        query.offset = None;
+        query.limit_values.clear();
+        if query.group_by.is_none() {
+            query.select = SelectClause::Sql("select json_object('count', count(*))".into());
+            query.select_columns.clear();
+            query.select_exprs.clear();
+            return query;
+        }
+        let sub = select_one(query.clone());
+        query.select = SelectClause::Sql(format!(
+            "select json_object('count', count(*)) from ({})",
+            sub.clause
+        ));
+        query.select_columns.clear();
+        query.select_exprs.clear();
+        query.from = None;
+        query.from_sql = None;
+        query.tables.clear();
+        query.joins = None;
+        query.r#where = None;
+        query.group_by = None;
+        query.values = sub.params;
+        query
+    }
+
+    /// Keeps the prepared statement in rusqlite's statement cache for queries that run often.
+    pub fn cached(mut self) -> Self {
+        self.options.cache = true;
+        self
+    }
+
+    /// Prepares the statement without caching it, undoing `cached` or `prep`,
+    /// for one off queries like huge `in_` lists that would push out the ones that get reused.
+    pub fn no_cache(mut self) -> Self {
+        self.options.cache = false;
+        self
+    }
+
+    /// Tries the query up to `attempts` times while the database is busy or locked.
+    pub fn retry(mut self, attempts: usize) -> Self {
+        self.options.attempts = attempts;
         self
     }
 
     fn sql_statement<T: Row>(&self) -> Sql {
         Sql {
             clause: self.sql::<T>(),
-            params: self.values.clone(),
+            params: self
+                .select_exprs
+                .iter()
+                .flat_map(|expr| &expr.params)
+                .chain(&self.set_values)
+                .chain(&self.values)
+                .chain(&self.limit_values)
+                .cloned()
+                .collect(),
+        }
+    }
+
+    /// What kind of statement this builds, from the builder calls made so far.
+    pub fn kind(&self) -> QueryKind {
+        if self.insert_into.is_some() {
+            QueryKind::Insert
+        } else if self.update.is_some() {
+            QueryKind::Update
+        } else if self.delete.is_some() {
+            QueryKind::Delete
+        } else if self.from.is_some() || !matches!(self.select, SelectClause::None) {
+            QueryKind::Select
+        } else {
+            QueryKind::Other
         }
     }
 
+    /// The values this query binds, in placeholder order, to log alongside `sql()`.
+    pub fn params(&self) -> Vec<Value> {
+        self.sql_statement::<usize>().params
+    }
+
     pub fn sql<T: Row>(&self) -> String {
-        let select = match &self.select {
+        let extra = self
+            .select_exprs
+            .iter()
+            .map(|expr| format!("'{}', {}", expr.name, expr.clause))
+            .collect::<Vec<_>>();
+        let select: Option<std::borrow::Cow<str>> = match &self.select {
             SelectClause::All => match &self.from {
                 Some(tbl) => {
-                    if self.tables.is_empty() {
-                        Some(format!("select {}", json_object(&tbl, true)).into())
+                    if self.tables.len() <= 1 {
+                        let tbl = self.tables.first().unwrap_or(tbl);
+                        Some(format!("select {}", json_object_with(tbl, true, &extra)).into())
                     } else {
-                        if self.tables.len() == 1 {
-                            let tables = self
-                                .tables
-                                .iter()
-                                .map(|tbl| json_object(tbl, true))
-                                .collect::<Vec<_>>()
-                                .join(",");
-                            Some(format!("select {}", tables).into())
-                        } else {
-                            let keys = T::column_names();
-                            let x = keys
-                                .iter()
-                                .zip(&self.tables)
-                                .map(|(x, y)| format!("'{}', {}", x, json_object(&y, false)))
-                                .collect::<Vec<_>>()
-                                .join(",");
-                            Some(format!("select json_object({})", x).into())
-                        }
+                        let keys = T::column_names();
+                        let x = keys
+                            .iter()
+                            .zip(&self.tables)
+                            .map(|(x, y)| format!("'{}', {}", x, json_object(y, false)))
+                            .chain(extra)
+                            .collect::<Vec<_>>()
+                            .join(",");
+                        Some(format!("select json_object({})", x).into())
                     }
                 }
                 None => None,
             },
-            SelectClause::Sql(s) => Some(s.clone().into()),
-            SelectClause::None => None,
-        };
-        let from: Option<Arc<str>> = match &self.from {
-            Some(Tbl { table_name, .. }) => match table_name {
-                Some(table_name) => Some(format!("from {}", table_name).into()),
-                None => None,
-            },
-            _ => None,
-        };
-        let inner_joins: Option<Arc<str>> = if let Some(inner_joins) = &self.joins {
-            Some(inner_joins.clone().into())
-        } else {
-            None
+            SelectClause::Sql(s) => {
+                if extra.is_empty() {
+                    Some(s.as_str().into())
+                } else {
+                    let tbl = Tbl {
+                        table_name: None,
+                        column_names: self.select_columns.clone(),
+                    };
+                    Some(format!("select {}", json_object_with(&tbl, true, &extra)).into())
+                }
+            }
+            SelectClause::None => {
+                if extra.is_empty() {
+                    None
+                } else {
+                    Some(format!("select json_object({})", extra.join(",")).into())
+                }
+            }
         };
 
-        vec![
-            select,
-            from,
-            self.insert_into.clone(),
-            self.default_values.clone(),
-            self.values_sql.clone(),
-            self.update.clone(),
-            self.set.clone(),
-            self.delete.clone(),
-            inner_joins.clone(),
-            self.r#where.clone(),
-            self.group_by.clone(),
-            self.order.clone(),
-            self.returning.clone(),
-            self.limit.clone(),
+        let mut sql = String::with_capacity(256);
+        if let Some(select) = &select {
+            push_part(&mut sql, select);
+        }
+        for part in [
+            &self.from_sql,
+            &self.insert_into,
+            &self.default_values,
+            &self.values_sql,
+            &self.on_conflict,
+            &self.update,
+            &self.set,
+            &self.delete,
+        ]
+        .into_iter()
+        .flatten()
+        {
+            push_part(&mut sql, part);
+        }
+        if let Some(joins) = &self.joins {
+            push_part(&mut sql, joins);
+        }
+        // returning goes before order and limit so deletes and updates can use all three
+        for part in [
+            &self.r#where,
+            &self.group_by,
+            &self.returning,
+            &self.order,
+            &self.limit,
+            &self.offset,
         ]
         .into_iter()
-        .filter(|x| x.is_some())
-        .map(|x| x.unwrap())
-        .collect::<Vec<_>>()
-        .join(" ")
-        .into()
+        .flatten()
+        {
+            push_part(&mut sql, part);
+        }
+
+        sql
     }
 
     pub async fn all<T>(self) -> Result<Vec<T>, Error>
     where
         T: Row + DeserializeOwned + Send + Sync + 'static,
     {
-        let rows = rows(&self.connection, self.sql_statement::<T>()).await?;
+        let rows = rows_with(self.connection, self.sql_statement::<T>(), &self.options).await?;
         Ok(rows)
     }
 
+    /// Rows keyed by column name for when the shape isn't known at compile time.
+    /// Joined tables come back as json `Value::Text`.
+    pub async fn all_dynamic(self) -> Result<Vec<BTreeMap<String, Value>>, Error> {
+        let rows: Vec<DynamicRow> = self.all().await?;
+        Ok(rows
+            .into_iter()
+            .map(|row| {
+                row.0
+                    .into_iter()
+                    .map(|(name, value)| (name, json_value(value)))
+                    .collect()
+            })
+            .collect())
+    }
+
     pub async fn first<T>(&self) -> Result<T, Error>
     where
         T: Row + DeserializeOwned + Send + Sync + 'static,
     {
-        let row = rows::<T>(&self.connection, self.sql_statement::<T>())
+        let row = rows_with::<T>(self.connection, self.sql_statement::<T>(), &self.options)
             .await?
             .into_iter()
             .nth(0)
@@ -615,11 +1302,13 @@ impl<'a> Query<'a> {
         Ok(row)
     }
 
+    /// Warms the statement cache with this query's statement and runs it `cached` from then on.
     pub async fn prep<T: Row + DeserializeOwned + Send + Sync + 'static>(
         self,
     ) -> Result<Self, Error> {
-        let sql = self.sql_statement::<T>();
-        self.connection
+        let this = self.cached();
+        let sql = this.sql_statement::<T>();
+        this.connection
             .call(move |conn| {
                 // this uses an internal Lru cache within rusqlite
                 // and uses the sql as the key to the cache
@@ -630,20 +1319,131 @@ impl<'a> Query<'a> {
             })
             .await?;
 
-        Ok(self)
+        Ok(this)
+    }
+
+    /// Builds the sql once, later calls only swap in new binds for the template's placeholders.
+    /// Template values must be non-null, a null turns into `is null` instead of a `?`.
+    pub fn prepare<T: Row>(&self) -> PreparedQuery<T> {
+        let sql = self.sql_statement::<T>();
+        PreparedQuery {
+            connection: self.connection.clone(),
+            sql: sql.clause.into(),
+            param_count: sql.params.len(),
+            options: self.cached_options(),
+            row: std::marker::PhantomData,
+        }
     }
 
     pub fn insert(mut self, table: impl Table) -> Self {
-        self.insert_into = Some(format!("insert into {}", table.table_name()).into());
-        self.tables.push(Tbl {
-            table_name: Some(table.table_name()),
-            column_names: table.column_names(),
-        });
+        self.insert_into = Some(fragment(format!(
+            "insert into {}",
+            quote_ident(table.table_name())
+        )));
+        self.tables.push(Tbl::new(&table));
         self
     }
 
+    /// `insert into table (columns) select ...`, an empty `columns` leaves out the column list
+    /// and a select without columns copies `*`.
+    pub fn insert_select(self, table: impl Table, columns: impl Select, mut select: Query) -> Self {
+        let table_name = table.table_name();
+        let mut query = self.insert(table);
+        let columns = columns
+            .columns()
+            .into_iter()
+            .map(|col| quote_ident(&unqualify(&col)))
+            .collect::<Vec<_>>();
+        let source = select
+            .select_columns
+            .iter()
+            .map(|col| col.to_string())
+            .chain(select.select_exprs.iter().map(|expr| expr.clause.clone()))
+            .collect::<Vec<_>>();
+        let expr_params = select
+            .select_exprs
+            .drain(..)
+            .flat_map(|expr| expr.params)
+            .collect::<Vec<_>>();
+        select.select = SelectClause::Sql(format!(
+            "select {}",
+            if source.is_empty() {
+                "*".into()
+            } else {
+                source.join(", ")
+            }
+        ));
+        let sql = select.sql_statement::<usize>();
+        query.insert_into = Some(
+            format!(
+                "insert into {}{} {}",
+                quote_ident(table_name),
+                if columns.is_empty() {
+                    "".into()
+                } else {
+                    format!(" ({})", columns.join(", "))
+                },
+                sql.clause
+            )
+            .into(),
+        );
+        query.values = expr_params.into_iter().chain(sql.params).collect();
+        query
+    }
+
+    /// `insert ... on conflict (conflict) do update set` every other column from `row`.
+    pub fn upsert(
+        self,
+        table: impl Table,
+        row: impl Serialize,
+        conflict: impl Select,
+    ) -> Result<Self, Error> {
+        let conflict = conflict
+            .columns()
+            .into_iter()
+            .map(|col| unqualify(&col))
+            .collect::<Vec<_>>();
+        let columns = Self::row_to_named_params(&row)?
+            .iter()
+            .map(|(name, _)| name.replacen(":", "", 1))
+            .filter(|name| !conflict.contains(name))
+            .map(|name| quote_ident(&name))
+            .collect::<Vec<_>>();
+        let conflict = conflict
+            .iter()
+            .map(|name| quote_ident(name))
+            .collect::<Vec<_>>();
+        let mut query = self.insert(table).values(row)?;
+        query.on_conflict = Some(
+            match columns.is_empty() {
+                true => format!("on conflict ({}) do nothing", conflict.join(",")),
+                false => format!(
+                    "on conflict ({}) do update set {}",
+                    conflict.join(","),
+                    columns
+                        .iter()
+                        .map(|name| format!("{} = excluded.{}", name, name))
+                        .collect::<Vec<_>>()
+                        .join(",")
+                ),
+            }
+            .into(),
+        );
+
+        Ok(query)
+    }
+
+    /// `insert or <mode> into table`, sqlite's shorthand conflict resolution.
+    pub fn insert_or(self, table: impl Table, mode: OnConflict) -> Self {
+        let table_name = table.table_name();
+        let mut query = self.insert(table);
+        query.insert_into =
+            Some(format!("insert or {} into {}", mode, quote_ident(table_name)).into());
+        query
+    }
+
     pub fn default_values(mut self) -> Self {
-        self.default_values = Some("default values".into());
+        self.default_values = Some(fragment("default values"));
         self
     }
 
@@ -652,11 +1452,13 @@ impl<'a> Query<'a> {
     }
 
     pub fn values(mut self, row: impl Serialize) -> Result<Self, Error> {
+        // column names and binds both come from this one list, so the nth value always
+        // lines up with the nth column whatever order the struct declares its fields in
         let named_params = Self::row_to_named_params(row)?;
 
         let column_names = named_params
             .iter()
-            .map(|(name, _)| name.replacen(":", "", 1).to_string())
+            .map(|(name, _)| quote_ident(&name.replacen(":", "", 1)))
             .collect::<Vec<_>>();
         let placeholders = named_params
             .iter()
@@ -665,13 +1467,7 @@ impl<'a> Query<'a> {
             .join(",");
         let values: Result<Vec<_>, Error> = named_params
             .iter()
-            .map(|(_, to_sql)| {
-                Ok(match to_sql.to_sql()? {
-                    rusqlite::types::ToSqlOutput::Borrowed(value_ref) => value_ref.into(),
-                    rusqlite::types::ToSqlOutput::Owned(value) => value,
-                    _ => unimplemented!(),
-                })
-            })
+            .map(|(_, to_sql)| to_value(to_sql.as_ref()))
             .collect();
 
         self.insert_into = match &self.insert_into {
@@ -689,12 +1485,64 @@ impl<'a> Query<'a> {
         Ok(self)
     }
 
+    /// Prepares `insert into t (..) values (..)` once for every column of the table
+    /// so the returned handle can insert many rows without rebuilding the sql.
+    pub async fn prepare_insert(self) -> Result<PreparedInsert, Error> {
+        let tbl =
+            match (&self.insert_into, self.tables.first()) {
+                (Some(_), Some(tbl)) => tbl,
+                _ => return Err(Error::Sql(
+                    "no table name found when calling prepare_insert. Try calling insert() first"
+                        .into(),
+                )),
+            };
+        let columns = tbl.column_names.clone();
+        let sql: Arc<str> = format!(
+            "insert into {} ({}) values ({})",
+            quote_ident(tbl.table_name.as_deref().unwrap_or_default()),
+            columns
+                .iter()
+                .map(|c| quote_ident(c))
+                .collect::<Vec<_>>()
+                .join(","),
+            columns
+                .iter()
+                .map(|c| format!(":{}", c))
+                .collect::<Vec<_>>()
+                .join(",")
+        )
+        .into();
+        let clause = sql.clone();
+        self.connection
+            .call(move |conn| {
+                let _ = conn.prepare_cached(&clause)?;
+
+                Ok(())
+            })
+            .await?;
+
+        Ok(PreparedInsert {
+            connection: self.connection.clone(),
+            sql,
+            columns,
+            options: self.cached_options(),
+        })
+    }
+
+    // prepared handles run the same sql over and over, so they always use the statement cache
+    fn cached_options(&self) -> QueryOptions {
+        QueryOptions {
+            cache: true,
+            ..self.options.clone()
+        }
+    }
+
     pub fn update(mut self, table: impl Table) -> Self {
-        self.update = Some(format!("update {}", table.table_name()).into());
-        self.tables.push(Tbl {
-            table_name: Some(table.table_name()),
-            column_names: table.column_names(),
-        });
+        self.update = Some(fragment(format!(
+            "update {}",
+            quote_ident(table.table_name())
+        )));
+        self.tables.push(Tbl::new(&table));
         self
     }
 
@@ -707,34 +1555,39 @@ impl<'a> Query<'a> {
             .collect::<Vec<_>>();
         let set = column_names
             .iter()
-            .map(|name| format!("{} = ?", name.replacen(":", "", 1)))
+            .map(|name| format!("{} = ?", quote_ident(&name.replacen(":", "", 1))))
             .collect::<Vec<_>>()
             .join(",");
         self.set = Some(format!("set {}", set).into());
+        // kept apart from the where binds so calling set after where_ doesn't drop them
         let values: Result<Vec<_>, Error> = named_params
             .iter()
-            .map(|(_, to_sql)| {
-                Ok(match to_sql.to_sql()? {
-                    rusqlite::types::ToSqlOutput::Borrowed(value_ref) => value_ref.into(),
-                    rusqlite::types::ToSqlOutput::Owned(value) => value,
-                    _ => unimplemented!(),
-                })
-            })
+            .map(|(_, to_sql)| to_value(to_sql.as_ref()))
             .collect();
-        self.values = values?;
+        self.set_values = values?;
 
         Ok(self)
     }
 
     pub fn delete(mut self, table: impl Table) -> Self {
-        self.delete = Some(format!("delete from {}", table.table_name()).into());
-        self.tables.push(Tbl {
-            table_name: Some(table.table_name()),
-            column_names: table.column_names(),
-        });
+        self.delete = Some(fragment(format!(
+            "delete from {}",
+            quote_ident(table.table_name())
+        )));
+        self.tables.push(Tbl::new(&table));
         self
     }
 
+    /// `delete from table where column in (...)`, an empty `ids` emits `in ()` and deletes nothing.
+    pub fn delete_where_in(
+        self,
+        table: impl Table,
+        column: impl ToColumn,
+        ids: Vec<impl ToValueColumn>,
+    ) -> Self {
+        self.delete(table).where_(in_(column, ids))
+    }
+
     pub async fn returning<T: Row + Serialize + DeserializeOwned + Send + Sync + 'static>(
         mut self,
     ) -> Result<T, Error> {
@@ -745,8 +1598,10 @@ impl<'a> Query<'a> {
             .collect::<Vec<_>>()
             .join(",");
         self.returning = Some(format!("returning {}", tables).into());
+        self.check_limited().await?;
 
-        let rows = rows::<T>(&self.connection, self.sql_statement::<T>()).await?;
+        let rows =
+            rows_with::<T>(self.connection, self.sql_statement::<T>(), &self.options).await?;
         if let Some(row) = rows.into_iter().nth(0) {
             Ok(row)
         } else {
@@ -757,12 +1612,234 @@ impl<'a> Query<'a> {
         }
     }
 
+    /// Like `returning` but only fetches `columns`, e.g. just the id after an insert.
+    pub async fn returning_cols<T: Row + DeserializeOwned + Send + Sync + 'static>(
+        mut self,
+        columns: impl Select,
+    ) -> Result<T, Error> {
+        let tbl = Tbl {
+            table_name: None,
+            column_names: columns.columns(),
+        };
+        self.returning = Some(format!("returning {}", json_object(&tbl, false)).into());
+        self.check_limited().await?;
+
+        rows_with::<T>(self.connection, self.sql_statement::<T>(), &self.options)
+            .await?
+            .into_iter()
+            .nth(0)
+            .ok_or(Error::RowNotFound)
+    }
+
+    // delete and update only take order by, limit and offset when sqlite was
+    // compiled with SQLITE_ENABLE_UPDATE_DELETE_LIMIT, the bundled build isn't
+    async fn check_limited(&self) -> Result<(), Error> {
+        if self.update.is_none() && self.delete.is_none() {
+            return Ok(());
+        }
+        if self.order.is_none() && self.limit.is_none() && self.offset.is_none() {
+            return Ok(());
+        }
+        let supported = match self.options.update_delete_limit.get() {
+            Some(supported) => *supported,
+            None => {
+                let supported = self
+                    .connection
+                    .call(|conn| {
+                        conn.query_row(
+                            "select sqlite_compileoption_used('ENABLE_UPDATE_DELETE_LIMIT')",
+                            [],
+                            |row| row.get::<_, bool>(0),
+                        )
+                    })
+                    .await?;
+                *self.options.update_delete_limit.get_or_init(|| supported)
+            }
+        };
+        match supported {
+            true => Ok(()),
+            false => Err(Error::Sql(
+                "order, limit and offset on delete or update need sqlite compiled with SQLITE_ENABLE_UPDATE_DELETE_LIMIT".into(),
+            )),
+        }
+    }
+
     pub async fn rows_affected(&self) -> Result<usize, Error> {
-        let rows_affected = execute(&self.connection, self.sql_statement::<usize>()).await?;
+        self.check_limited().await?;
+        let rows_affected = execute_with(
+            self.connection,
+            self.sql_statement::<usize>(),
+            &self.options,
+        )
+        .await?;
         Ok(rows_affected)
     }
 }
 
+fn to_value(to_sql: &dyn ToSql) -> Result<Value, Error> {
+    match to_sql.to_sql() {
+        Ok(rusqlite::types::ToSqlOutput::Borrowed(value_ref)) => Ok(value_ref.into()),
+        Ok(rusqlite::types::ToSqlOutput::Owned(value)) => Ok(value),
+        Ok(output) => Err(Error::ToSql(format!("{:?} can't be bound", output))),
+        Err(err) => Err(Error::ToSql(err.to_string())),
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct PreparedInsert {
+    connection: tokio_rusqlite::Connection,
+    sql: Arc<str>,
+    columns: Vec<Cow<'static, str>>,
+    options: QueryOptions,
+}
+
+impl PreparedInsert {
+    pub fn sql(&self) -> &str {
+        &self.sql
+    }
+
+    pub async fn execute(&self, row: impl Serialize) -> Result<usize, Error> {
+        let named_params = serde_rusqlite::to_params_named(row)?;
+        // each :column shows up once, in column order, so the binds can go in by position.
+        // a column the row doesn't have is bound as null, like an unbound named param
+        let params = self
+            .columns
+            .iter()
+            .map(|col| {
+                match named_params
+                    .iter()
+                    .find(|(name, _)| name.trim_start_matches(':') == col)
+                {
+                    Some((_, to_sql)) => to_value(to_sql.as_ref()),
+                    None => Ok(Value::Null),
+                }
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+        let sql = Sql {
+            clause: self.sql.to_string(),
+            params,
+        };
+
+        execute_with(&self.connection, sql, &self.options).await
+    }
+}
+
+pub struct PreparedQuery<T> {
+    connection: tokio_rusqlite::Connection,
+    sql: Arc<str>,
+    param_count: usize,
+    options: QueryOptions,
+    row: std::marker::PhantomData<fn() -> T>,
+}
+
+impl<T> Clone for PreparedQuery<T> {
+    fn clone(&self) -> Self {
+        Self {
+            connection: self.connection.clone(),
+            sql: self.sql.clone(),
+            param_count: self.param_count,
+            options: self.options.clone(),
+            row: std::marker::PhantomData,
+        }
+    }
+}
+
+impl<T: DeserializeOwned + Send + 'static> PreparedQuery<T> {
+    pub fn sql(&self) -> &str {
+        &self.sql
+    }
+
+    fn check(&self, params: &[Value]) -> Result<(), Error> {
+        match params.len() == self.param_count {
+            true => Ok(()),
+            false => Err(Error::Sql(format!(
+                "expected {} params, got {}",
+                self.param_count,
+                params.len()
+            ))),
+        }
+    }
+
+    pub async fn all(&self, params: Vec<Value>) -> Result<Vec<T>, Error> {
+        self.check(&params)?;
+        let sql = Sql {
+            clause: self.sql.to_string(),
+            params,
+        };
+
+        rows_with(&self.connection, sql, &self.options).await
+    }
+
+    pub async fn first(&self, params: Vec<Value>) -> Result<T, Error> {
+        self.all(params)
+            .await?
+            .into_iter()
+            .next()
+            .ok_or(Error::RowNotFound)
+    }
+
+    pub async fn execute(&self, params: Vec<Value>) -> Result<usize, Error> {
+        self.check(&params)?;
+        let sql = Sql {
+            clause: self.sql.to_string(),
+            params,
+        };
+
+        execute_with(&self.connection, sql, &self.options).await
+    }
+}
+
+/// The row of a `select count(*)` from `clone_for_count`.
+#[derive(Row, Serialize, serde::Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RowCount {
+    pub count: i64,
+}
+
+/// Just the number from a `select count(*)`, e.g. `let Count(n) = query.clone_for_count().first().await?`.
+#[derive(serde::Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(from = "RowCount")]
+pub struct Count(pub i64);
+
+impl From<RowCount> for Count {
+    fn from(row: RowCount) -> Self {
+        Count(row.count)
+    }
+}
+
+impl From<Count> for i64 {
+    fn from(count: Count) -> Self {
+        count.0
+    }
+}
+
+impl Row for Count {
+    fn column_names() -> Vec<&'static str> {
+        RowCount::column_names()
+    }
+}
+
+#[derive(serde::Deserialize)]
+struct DynamicRow(BTreeMap<String, serde_json::Value>);
+
+impl Row for DynamicRow {
+    fn column_names() -> Vec<&'static str> {
+        vec![]
+    }
+}
+
+fn json_value(value: serde_json::Value) -> Value {
+    match value {
+        serde_json::Value::Null => Value::Null,
+        serde_json::Value::Bool(b) => Value::Integer(b as i64),
+        serde_json::Value::Number(n) => match n.as_i64() {
+            Some(i) => Value::Integer(i),
+            None => Value::Real(n.as_f64().unwrap_or_default()),
+        },
+        serde_json::Value::String(s) => Value::Text(s),
+        value => Value::Text(value.to_string()),
+    }
+}
+
 impl Row for usize {
     fn column_names() -> Vec<&'static str> {
         vec![]
@@ -773,6 +1850,55 @@ pub fn placeholder() -> &'static str {
     "?"
 }
 
+// table level clauses repeat on every query, share one allocation per distinct fragment.
+// only fed fragments built from table names and aliases so the cache stays small, and
+// it's per thread so building queries never waits on a lock
+fn fragment(s: impl AsRef<str>) -> Arc<str> {
+    thread_local! {
+        static FRAGMENTS: std::cell::RefCell<std::collections::HashSet<Arc<str>>> =
+            Default::default();
+    }
+    FRAGMENTS.with_borrow_mut(|fragments| match fragments.get(s.as_ref()) {
+        Some(fragment) => fragment.clone(),
+        None => {
+            let fragment: Arc<str> = s.as_ref().into();
+            fragments.insert(fragment.clone());
+            fragment
+        }
+    })
+}
+
+/// A column qualified by a table alias, `alias.column`.
+#[derive(Clone, Debug)]
+pub struct Aliased(Arc<str>);
+
+pub fn aliased(alias: &str, col: impl ToColumn) -> Aliased {
+    Aliased(
+        format!(
+            "{}.{}",
+            quote_ident(alias),
+            quote_ident(&unqualify(&col.to_column()))
+        )
+        .into(),
+    )
+}
+
+impl ToColumn for Aliased {
+    fn to_column(&self) -> Cow<'static, str> {
+        self.0.to_string().into()
+    }
+}
+
+impl ToValueColumn for Aliased {
+    fn to_value(&self) -> Option<Value> {
+        None
+    }
+
+    fn to_column(&self) -> Option<Cow<'static, str>> {
+        Some(self.0.to_string().into())
+    }
+}
+
 #[derive(Clone, Copy, Debug, Default)]
 pub struct Null<T: ToColumn>(pub T);
 
@@ -789,40 +1915,86 @@ pub struct Integer(pub &'static str);
 pub struct Real(pub &'static str);
 
 pub trait ToColumn {
-    fn to_column(&self) -> &'static str;
+    fn to_column(&self) -> Cow<'static, str>;
 }
 
 impl ToColumn for Text {
-    fn to_column(&self) -> &'static str {
-        self.0
+    fn to_column(&self) -> Cow<'static, str> {
+        self.0.into()
     }
 }
 
 impl<T: ToColumn> ToColumn for Null<T> {
-    fn to_column(&self) -> &'static str {
+    fn to_column(&self) -> Cow<'static, str> {
         self.0.to_column()
     }
 }
 
 impl ToColumn for Integer {
-    fn to_column(&self) -> &'static str {
-        self.0
+    fn to_column(&self) -> Cow<'static, str> {
+        self.0.into()
     }
 }
 
 impl ToColumn for Blob {
-    fn to_column(&self) -> &'static str {
-        self.0
+    fn to_column(&self) -> Cow<'static, str> {
+        self.0.into()
     }
 }
 
 impl ToColumn for Real {
-    fn to_column(&self) -> &'static str {
-        self.0
+    fn to_column(&self) -> Cow<'static, str> {
+        self.0.into()
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct WhereBuilder {
+    predicates: Vec<Sql>,
+}
+
+impl WhereBuilder {
+    pub fn push(&mut self, sql: Sql) -> &mut Self {
+        self.predicates.push(sql);
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.predicates.is_empty()
+    }
+}
+
+/// `1=1`, `and` drops it and `or` with it is always true.
+pub fn always() -> Sql {
+    Sql {
+        clause: "1=1".into(),
+        params: vec![],
     }
 }
 
+/// `1=0`, `or` drops it and `and` with it is never true.
+pub fn never() -> Sql {
+    Sql {
+        clause: "1=0".into(),
+        params: vec![],
+    }
+}
+
+fn is_always(sql: &Sql) -> bool {
+    sql.clause == "1=1" && sql.params.is_empty()
+}
+
+fn is_never(sql: &Sql) -> bool {
+    sql.clause == "1=0" && sql.params.is_empty()
+}
+
 pub fn and(left: Sql, right: Sql) -> Sql {
+    match (&left, &right) {
+        (l, _) if is_always(l) => return right,
+        (_, r) if is_always(r) => return left,
+        (l, r) if is_never(l) || is_never(r) => return never(),
+        _ => {}
+    }
     let mut params: Vec<Value> = vec![];
     params.extend(left.params);
     params.extend(right.params);
@@ -834,6 +2006,12 @@ pub fn and(left: Sql, right: Sql) -> Sql {
 }
 
 pub fn or(left: Sql, right: Sql) -> Sql {
+    match (&left, &right) {
+        (l, _) if is_never(l) => return right,
+        (_, r) if is_never(r) => return left,
+        (l, r) if is_always(l) || is_always(r) => return always(),
+        _ => {}
+    }
     let mut params: Vec<Value> = vec![];
     params.extend(left.params);
     params.extend(right.params);
@@ -846,7 +2024,13 @@ pub fn or(left: Sql, right: Sql) -> Sql {
 
 pub trait ToValueColumn {
     fn to_value(&self) -> Option<Value>;
-    fn to_column(&self) -> Option<&'static str>;
+    fn to_column(&self) -> Option<Cow<'static, str>>;
+
+    /// Whether this binds as a `?` param. `Value::Null` never does, it's written into
+    /// the sql as `null`, and neither do columns, they're written in by name.
+    fn is_bindable(&self) -> bool {
+        !matches!(self.to_value(), None | Some(Value::Null))
+    }
 
     fn to_params(&self) -> Vec<Value> {
         match self.to_value() {
@@ -858,13 +2042,13 @@ pub trait ToValueColumn {
         }
     }
 
-    fn to_placeholder(&self) -> &'static str {
+    fn to_placeholder(&self) -> Cow<'static, str> {
         match self.to_value() {
             Some(value) => match value {
-                Value::Null => "null",
-                _ => "?",
+                Value::Null => "null".into(),
+                _ => "?".into(),
             },
-            None => self.to_column().unwrap_or(""),
+            None => self.to_column().unwrap_or_default(),
         }
     }
 }
@@ -874,7 +2058,7 @@ impl<T: ToColumn> ToValueColumn for Null<T> {
         None
     }
 
-    fn to_column(&self) -> Option<&'static str> {
+    fn to_column(&self) -> Option<Cow<'static, str>> {
         Some(self.0.to_column())
     }
 }
@@ -884,8 +2068,8 @@ impl ToValueColumn for Value {
         Some(self.clone())
     }
 
-    fn to_column(&self) -> Option<&'static str> {
-        Some("?")
+    fn to_column(&self) -> Option<Cow<'static, str>> {
+        Some("?".into())
     }
 }
 
@@ -894,8 +2078,8 @@ impl ToValueColumn for String {
         Some(Value::Text(self.clone()))
     }
 
-    fn to_column(&self) -> Option<&'static str> {
-        Some("?")
+    fn to_column(&self) -> Option<Cow<'static, str>> {
+        Some("?".into())
     }
 }
 
@@ -904,8 +2088,28 @@ impl ToValueColumn for &str {
         Some(Value::Text(self.to_string()))
     }
 
-    fn to_column(&self) -> Option<&'static str> {
-        Some("?")
+    fn to_column(&self) -> Option<Cow<'static, str>> {
+        Some("?".into())
+    }
+}
+
+impl ToValueColumn for Arc<str> {
+    fn to_value(&self) -> Option<Value> {
+        Some(Value::Text(self.to_string()))
+    }
+
+    fn to_column(&self) -> Option<Cow<'static, str>> {
+        Some("?".into())
+    }
+}
+
+impl ToValueColumn for std::borrow::Cow<'_, str> {
+    fn to_value(&self) -> Option<Value> {
+        Some(Value::Text(self.to_string()))
+    }
+
+    fn to_column(&self) -> Option<Cow<'static, str>> {
+        Some("?".into())
     }
 }
 
@@ -914,8 +2118,8 @@ impl ToValueColumn for i64 {
         Some(Value::Integer(*self))
     }
 
-    fn to_column(&self) -> Option<&'static str> {
-        Some("?")
+    fn to_column(&self) -> Option<Cow<'static, str>> {
+        Some("?".into())
     }
 }
 
@@ -924,8 +2128,8 @@ impl ToValueColumn for f64 {
         Some(Value::Real(*self))
     }
 
-    fn to_column(&self) -> Option<&'static str> {
-        Some("?")
+    fn to_column(&self) -> Option<Cow<'static, str>> {
+        Some("?".into())
     }
 }
 
@@ -934,8 +2138,8 @@ impl ToValueColumn for Vec<u8> {
         Some(Value::Blob(self.to_vec()))
     }
 
-    fn to_column(&self) -> Option<&'static str> {
-        Some("?")
+    fn to_column(&self) -> Option<Cow<'static, str>> {
+        Some("?".into())
     }
 }
 
@@ -944,83 +2148,409 @@ impl ToValueColumn for &[u8] {
         Some(Value::Blob(self.to_vec()))
     }
 
-    fn to_column(&self) -> Option<&'static str> {
-        Some("?")
+    fn to_column(&self) -> Option<Cow<'static, str>> {
+        Some("?".into())
     }
 }
 
-impl ToValueColumn for Text {
+#[cfg(feature = "json")]
+impl ToValueColumn for serde_json::Value {
     fn to_value(&self) -> Option<Value> {
-        None
+        Some(Value::Text(self.to_string()))
     }
 
-    fn to_column(&self) -> Option<&'static str> {
-        Some(self.0)
+    fn to_column(&self) -> Option<Cow<'static, str>> {
+        None
     }
 }
 
-impl ToValueColumn for Integer {
-    fn to_value(&self) -> Option<Value> {
-        None
+/// Stores a `serde_json::Value` field as json text, use it with `#[serde(with = "ryzz::json")]`.
+#[cfg(feature = "json")]
+pub mod json {
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(
+        value: &serde_json::Value,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&value.to_string())
     }
 
-    fn to_column(&self) -> Option<&'static str> {
-        Some(self.0)
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<serde_json::Value, D::Error> {
+        match serde_json::Value::deserialize(deserializer)? {
+            serde_json::Value::String(text) => {
+                serde_json::from_str(&text).map_err(D::Error::custom)
+            }
+            value => Ok(value),
+        }
     }
 }
 
-impl ToValueColumn for Blob {
-    fn to_value(&self) -> Option<Value> {
-        None
+/// Stores a field as its decimal text, use it with `#[serde(with = "ryzz::as_text")]` on
+/// `i128` and `u128` fields so values past `i64::MAX` read back exactly.
+/// Bind them in predicates with `as_text::value`, a `ToValueColumn` impl on the
+/// integers themselves would break inference for plain integer literals.
+pub mod as_text {
+    use rusqlite::types::Value;
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+    use std::{fmt::Display, str::FromStr};
+
+    pub fn value<T: Display>(value: T) -> Value {
+        Value::Text(value.to_string())
     }
 
-    fn to_column(&self) -> Option<&'static str> {
-        Some(self.0)
+    pub fn serialize<T: Display, S: Serializer>(
+        value: &T,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(value)
+    }
+
+    pub fn deserialize<'de, T, D>(deserializer: D) -> Result<T, D::Error>
+    where
+        T: FromStr,
+        T::Err: Display,
+        D: Deserializer<'de>,
+    {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(D::Error::custom)
     }
 }
 
-impl ToValueColumn for Real {
+#[cfg(feature = "decimal")]
+impl ToValueColumn for rust_decimal::Decimal {
     fn to_value(&self) -> Option<Value> {
+        Some(Value::Text(self.to_string()))
+    }
+
+    fn to_column(&self) -> Option<Cow<'static, str>> {
         None
     }
+}
+
+/// Stores a `rust_decimal::Decimal` field as its exact decimal text, e.g. `"12.3400"`,
+/// use it with `#[serde(with = "ryzz::decimal")]`. Text columns compare as strings, not numbers.
+#[cfg(feature = "decimal")]
+pub mod decimal {
+    use rust_decimal::Decimal;
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+    use std::str::FromStr;
+
+    pub fn serialize<S: Serializer>(value: &Decimal, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&value.to_string())
+    }
 
-    fn to_column(&self) -> Option<&'static str> {
-        Some(self.0)
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Decimal, D::Error> {
+        let text = String::deserialize(deserializer)?;
+        Decimal::from_str(&text).map_err(D::Error::custom)
     }
 }
 
-pub fn eq(left: impl ToColumn, right: impl ToValueColumn) -> Sql {
-    let value = right.to_value();
-    let op = match value {
-        Some(val) => match val {
-            Value::Null => "is",
-            _ => "=",
-        },
-        None => "=",
-    };
-    let params = right.to_params();
-    let clause = format!("{} {} {}", left.to_column(), op, right.to_placeholder());
-    Sql { clause, params }
+/// A `Value` that serializes tagged by variant, e.g. `{"Integer":1}`, for shipping params
+/// and rows over the wire. Blobs are base64 text, `Value::Null` is `"Null"`.
+#[derive(Serialize, serde::Deserialize, Clone, Debug, PartialEq)]
+#[serde(from = "Tagged", into = "Tagged")]
+pub struct TaggedValue(pub Value);
+
+#[derive(Serialize, serde::Deserialize)]
+enum Tagged {
+    Null,
+    Integer(i64),
+    Real(f64),
+    Text(String),
+    Blob(#[serde(with = "base64")] Vec<u8>),
 }
 
-pub fn ne(left: impl ToColumn, right: impl ToValueColumn) -> Sql {
-    let value = right.to_value();
-    let op = if let Some(value) = value {
-        match value {
-            Value::Null => "is not",
-            _ => "!=",
+impl From<Tagged> for TaggedValue {
+    fn from(tagged: Tagged) -> Self {
+        TaggedValue(match tagged {
+            Tagged::Null => Value::Null,
+            Tagged::Integer(i) => Value::Integer(i),
+            Tagged::Real(f) => Value::Real(f),
+            Tagged::Text(s) => Value::Text(s),
+            Tagged::Blob(b) => Value::Blob(b),
+        })
+    }
+}
+
+impl From<TaggedValue> for Tagged {
+    fn from(value: TaggedValue) -> Self {
+        match value.0 {
+            Value::Null => Tagged::Null,
+            Value::Integer(i) => Tagged::Integer(i),
+            Value::Real(f) => Tagged::Real(f),
+            Value::Text(s) => Tagged::Text(s),
+            Value::Blob(b) => Tagged::Blob(b),
         }
-    } else {
-        "!="
-    };
-    let params = right.to_params();
-    Sql {
-        clause: format!("{} {} {}", left.to_column(), op, right.to_placeholder()),
-        params,
     }
 }
 
-pub fn gt(left: impl ToColumn, right: impl ToValueColumn) -> Sql {
+impl From<Value> for TaggedValue {
+    fn from(value: Value) -> Self {
+        TaggedValue(value)
+    }
+}
+
+impl From<TaggedValue> for Value {
+    fn from(value: TaggedValue) -> Self {
+        value.0
+    }
+}
+
+impl ToValueColumn for TaggedValue {
+    fn to_value(&self) -> Option<Value> {
+        Some(self.0.clone())
+    }
+
+    fn to_column(&self) -> Option<Cow<'static, str>> {
+        Some("?".into())
+    }
+}
+
+// standard alphabet with padding
+mod base64 {
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+    pub fn serialize<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        let mut text = String::with_capacity(bytes.len().div_ceil(3) * 4);
+        for chunk in bytes.chunks(3) {
+            let n = chunk
+                .iter()
+                .enumerate()
+                .fold(0u32, |n, (i, &b)| n | (b as u32) << (16 - 8 * i));
+            for i in 0..4 {
+                match i <= chunk.len() {
+                    true => text.push(ALPHABET[(n >> (18 - 6 * i) & 63) as usize] as char),
+                    false => text.push('='),
+                }
+            }
+        }
+        serializer.serialize_str(&text)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        let text = String::deserialize(deserializer)?;
+        let text = text.trim_end_matches('=');
+        let mut bytes = Vec::with_capacity(text.len() * 3 / 4);
+        let mut n = 0u32;
+        for (i, c) in text.bytes().enumerate() {
+            let Some(digit) = ALPHABET.iter().position(|&a| a == c) else {
+                return Err(D::Error::custom(format!(
+                    "invalid base64 character {:?}",
+                    c as char
+                )));
+            };
+            n = n << 6 | digit as u32;
+            if i % 4 == 3 {
+                bytes.extend_from_slice(&n.to_be_bytes()[1..]);
+                n = 0;
+            }
+        }
+        match text.len() % 4 {
+            0 => {}
+            2 => bytes.push((n >> 4) as u8),
+            3 => bytes.extend_from_slice(&((n >> 2) as u16).to_be_bytes()),
+            _ => return Err(D::Error::custom("invalid base64 length")),
+        }
+        Ok(bytes)
+    }
+}
+
+impl ToValueColumn for Text {
+    fn to_value(&self) -> Option<Value> {
+        None
+    }
+
+    fn to_column(&self) -> Option<Cow<'static, str>> {
+        Some(self.0.into())
+    }
+}
+
+impl ToValueColumn for Integer {
+    fn to_value(&self) -> Option<Value> {
+        None
+    }
+
+    fn to_column(&self) -> Option<Cow<'static, str>> {
+        Some(self.0.into())
+    }
+}
+
+impl ToValueColumn for Blob {
+    fn to_value(&self) -> Option<Value> {
+        None
+    }
+
+    fn to_column(&self) -> Option<Cow<'static, str>> {
+        Some(self.0.into())
+    }
+}
+
+impl ToValueColumn for Real {
+    fn to_value(&self) -> Option<Value> {
+        None
+    }
+
+    fn to_column(&self) -> Option<Cow<'static, str>> {
+        Some(self.0.into())
+    }
+}
+
+/// Values a column of this sqlite type can be compared with, checked by the `typed` comparisons.
+/// `Value`, `TaggedValue` and `Aliased` columns are untyped and go with anything.
+pub trait Accepts<V> {}
+
+macro_rules! impl_accepts {
+    ($column:ty: $($value:ty),+) => {
+        $(impl Accepts<$value> for $column {})+
+        impl Accepts<$column> for $column {}
+        impl Accepts<Null<$column>> for $column {}
+        impl Accepts<Value> for $column {}
+        impl Accepts<TaggedValue> for $column {}
+        impl Accepts<Aliased> for $column {}
+    };
+}
+
+impl_accepts!(Integer: i64);
+impl_accepts!(Real: f64, i64, Integer);
+impl_accepts!(Text: String, &str, Arc<str>, std::borrow::Cow<'_, str>);
+impl_accepts!(Blob: Vec<u8>, &[u8]);
+
+#[cfg(feature = "json")]
+impl Accepts<serde_json::Value> for Text {}
+
+#[cfg(feature = "decimal")]
+impl Accepts<rust_decimal::Decimal> for Text {}
+
+impl<C: ToColumn + Accepts<V>, V> Accepts<V> for Null<C> {}
+
+// method style comparisons, `accounts.id.eq(1)`, checked like the `typed` functions
+macro_rules! impl_comparisons {
+    ($([$($generics:tt)*] $column:ty),+) => {
+        $(impl<$($generics)*> $column {
+            pub fn eq<V: ToValueColumn>(self, right: V) -> Sql
+            where
+                Self: Accepts<V>,
+            {
+                typed::eq(self, right)
+            }
+
+            pub fn ne<V: ToValueColumn>(self, right: V) -> Sql
+            where
+                Self: Accepts<V>,
+            {
+                typed::ne(self, right)
+            }
+
+            pub fn gt<V: ToValueColumn>(self, right: V) -> Sql
+            where
+                Self: Accepts<V>,
+            {
+                typed::gt(self, right)
+            }
+
+            pub fn lt<V: ToValueColumn>(self, right: V) -> Sql
+            where
+                Self: Accepts<V>,
+            {
+                typed::lt(self, right)
+            }
+
+            pub fn gte<V: ToValueColumn>(self, right: V) -> Sql
+            where
+                Self: Accepts<V>,
+            {
+                typed::gte(self, right)
+            }
+
+            pub fn lte<V: ToValueColumn>(self, right: V) -> Sql
+            where
+                Self: Accepts<V>,
+            {
+                typed::lte(self, right)
+            }
+        })+
+    };
+}
+
+impl_comparisons!([] Integer, [] Real, [] Text, [] Blob, [C: ToColumn] Null<C>);
+
+/// The comparisons again, but `right` has to fit the column's sqlite type,
+/// `typed::eq(accounts.id, "notanumber")` doesn't compile.
+pub mod typed {
+    use crate::{Accepts, Sql, ToColumn, ToValueColumn};
+
+    pub fn eq<C: ToColumn + Accepts<V>, V: ToValueColumn>(left: C, right: V) -> Sql {
+        crate::eq(left, right)
+    }
+
+    pub fn ne<C: ToColumn + Accepts<V>, V: ToValueColumn>(left: C, right: V) -> Sql {
+        crate::ne(left, right)
+    }
+
+    pub fn gt<C: ToColumn + Accepts<V>, V: ToValueColumn>(left: C, right: V) -> Sql {
+        crate::gt(left, right)
+    }
+
+    pub fn lt<C: ToColumn + Accepts<V>, V: ToValueColumn>(left: C, right: V) -> Sql {
+        crate::lt(left, right)
+    }
+
+    pub fn gte<C: ToColumn + Accepts<V>, V: ToValueColumn>(left: C, right: V) -> Sql {
+        crate::gte(left, right)
+    }
+
+    pub fn lte<C: ToColumn + Accepts<V>, V: ToValueColumn>(left: C, right: V) -> Sql {
+        crate::lte(left, right)
+    }
+}
+
+pub fn eq(left: impl ToColumn, right: impl ToValueColumn) -> Sql {
+    let value = right.to_value();
+    let op = match value {
+        Some(Value::Null) => "is",
+        _ => "=",
+    };
+    let params = right.to_params();
+    let clause = format!("{} {} {}", left.to_column(), op, right.to_placeholder());
+    Sql { clause, params }
+}
+
+pub fn ne(left: impl ToColumn, right: impl ToValueColumn) -> Sql {
+    let value = right.to_value();
+    let op = match value {
+        Some(Value::Null) => "is not",
+        _ => "!=",
+    };
+    let params = right.to_params();
+    Sql {
+        clause: format!("{} {} {}", left.to_column(), op, right.to_placeholder()),
+        params,
+    }
+}
+
+/// Null safe equality, `null is null` is true where `null = null` is null.
+pub fn is(left: impl ToColumn, right: impl ToValueColumn) -> Sql {
+    Sql {
+        clause: format!("{} is {}", left.to_column(), right.to_placeholder()),
+        params: right.to_params(),
+    }
+}
+
+/// Null safe inequality, `'a' is not null` is true where `'a' != null` is null.
+pub fn is_not(left: impl ToColumn, right: impl ToValueColumn) -> Sql {
+    Sql {
+        clause: format!("{} is not {}", left.to_column(), right.to_placeholder()),
+        params: right.to_params(),
+    }
+}
+
+pub fn gt(left: impl ToColumn, right: impl ToValueColumn) -> Sql {
     Sql {
         clause: format!("{} > ?", left.to_column()),
         params: right.to_params(),
@@ -1055,6 +2585,68 @@ pub fn like(left: impl ToColumn, right: impl ToValueColumn) -> Sql {
     }
 }
 
+pub fn not_like(left: impl ToColumn, right: impl ToValueColumn) -> Sql {
+    Sql {
+        clause: format!("{} not like ?", left.to_column()),
+        params: right.to_params(),
+    }
+}
+
+/// Case sensitive unix style matching with `*` and `?`.
+pub fn glob(left: impl ToColumn, right: impl ToValueColumn) -> Sql {
+    Sql {
+        clause: format!("{} glob ?", left.to_column()),
+        params: right.to_params(),
+    }
+}
+
+pub fn not_glob(left: impl ToColumn, right: impl ToValueColumn) -> Sql {
+    Sql {
+        clause: format!("{} not glob ?", left.to_column()),
+        params: right.to_params(),
+    }
+}
+
+/// `like` where `escape` marks the next `%` or `_` as a literal, pair it with `escape_like`.
+pub fn like_escape(left: impl ToColumn, right: impl ToValueColumn, escape: char) -> Sql {
+    Sql {
+        clause: format!(
+            "{} like ? escape '{}'",
+            left.to_column(),
+            escape.to_string().replace('\'', "''")
+        ),
+        params: right.to_params(),
+    }
+}
+
+/// Escapes `%`, `_` and `escape` itself so user input only matches literally.
+pub fn escape_like(input: &str, escape: char) -> String {
+    let mut escaped = String::with_capacity(input.len());
+    for c in input.chars() {
+        if c == '%' || c == '_' || c == escape {
+            escaped.push(escape);
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// `left like '%term%' escape '\'` with `term` matched literally. Like sqlite's `like`,
+/// ascii letters match regardless of case.
+pub fn contains(left: impl ToColumn, term: &str) -> Sql {
+    like_escape(left, format!("%{}%", escape_like(term, '\\')), '\\')
+}
+
+/// `left like 'term%' escape '\'` with `term` matched literally.
+pub fn starts_with(left: impl ToColumn, term: &str) -> Sql {
+    like_escape(left, format!("{}%", escape_like(term, '\\')), '\\')
+}
+
+/// `left like '%term' escape '\'` with `term` matched literally.
+pub fn ends_with(left: impl ToColumn, term: &str) -> Sql {
+    like_escape(left, format!("%{}", escape_like(term, '\\')), '\\')
+}
+
 #[deprecated(since = "0.1.0", note = "please use `in_` instead")]
 pub fn r#in(left: impl ToColumn, right: Vec<impl ToValueColumn>) -> Sql {
     Sql {
@@ -1084,580 +2676,6706 @@ pub fn in_(left: impl ToColumn, right: Vec<impl ToValueColumn>) -> Sql {
     }
 }
 
-pub trait Table {
-    fn new() -> Self
-    where
-        Self: Sized + Clone + Send + Sync;
-    fn table_name(&self) -> &'static str;
-    fn table_alias(&self) -> Option<&'static str>;
-    fn struct_name(&self) -> &'static str;
-    fn column_names(&self) -> Vec<&'static str>;
-    fn create_table_sql(&self) -> &'static str;
-    fn add_column_sql(&self, column_name: &str) -> String;
+/// An empty `right` emits `not in ()`, sqlite takes that as true for every row,
+/// null columns included, so it excludes nothing.
+pub fn not_in(left: impl ToColumn, right: Vec<impl ToValueColumn>) -> Sql {
+    Sql {
+        clause: format!(
+            "{} not in ({})",
+            left.to_column(),
+            right.iter().map(|_| "?").collect::<Vec<&str>>().join(",")
+        ),
+        params: right
+            .into_iter()
+            .filter_map(|val| val.to_value())
+            .collect::<Vec<Value>>(),
+    }
 }
 
-pub trait Row
-where
-    Self: DeserializeOwned + Send + Sync,
-{
-    fn column_names() -> Vec<&'static str>;
+/// `exists (select 1 from ...)`, the subquery can compare against outer columns with
+/// `eq(child.parent_id, parent.id)` to make it correlated.
+pub fn exists_subquery(sub: Query) -> Sql {
+    let sql = select_one(sub);
+    Sql {
+        clause: format!("exists ({})", sql.clause),
+        params: sql.params,
+    }
 }
 
-#[derive(thiserror::Error, Debug)]
-pub enum Error {
-    #[error("database connection closed")]
-    ConnectionClosed,
-    #[error("database connection closing: {0}")]
-    Close(String),
-    #[error("unique constraint failed: {0}")]
-    UniqueConstraint(String),
-    #[error("database error: {0}")]
-    TokioRusqlite(#[from] tokio_rusqlite::Error),
-    #[error("database error: {0}")]
-    Rusqlite(#[from] rusqlite::Error),
-    #[error("missing from statement in sql query")]
-    MissingFrom,
-    #[error("error inserting record {0}")]
-    InsertError(String),
-    #[error("error converting value {0}")]
-    SqlConversion(String),
-    #[error("error building sql {0}")]
-    Sql(String),
-    #[error("could not find the row")]
-    RowNotFound,
-    #[error("could not deserialize rows {0}")]
-    Deserialize(#[from] serde_json::Error),
-    #[error("serialize error: {0}")]
-    Serialize(#[from] serde_rusqlite::Error),
+/// `not exists (select 1 from ...)`
+pub fn not_exists_subquery(sub: Query) -> Sql {
+    let sql = select_one(sub);
+    Sql {
+        clause: format!("not exists ({})", sql.clause),
+        params: sql.params,
+    }
 }
 
-#[derive(Debug)]
-pub struct Sql {
-    pub clause: String,
-    pub params: Vec<Value>,
+/// `column > all (select x ...)`, emulated as `not exists (... and x >= column)`.
+/// `sub` selects exactly one column, nulls in it are skipped and an empty `sub` matches every row.
+pub fn gt_all(column: impl ToColumn, sub: Query) -> Result<Sql, Error> {
+    let x = single_column(&sub)?;
+    Ok(not_exists_subquery(and_where(
+        sub,
+        format!("{} >= {}", x, column.to_column()),
+    )))
 }
 
-pub struct Index<'a> {
-    unique: bool,
-    name: &'a str,
-    table: &'a str,
-    columns: String,
+/// `column < any (select x ...)`, emulated as `exists (... and column < x)`.
+/// `sub` selects exactly one column, an empty `sub` matches no rows.
+pub fn lt_any(column: impl ToColumn, sub: Query) -> Result<Sql, Error> {
+    let x = single_column(&sub)?;
+    Ok(exists_subquery(and_where(
+        sub,
+        format!("{} < {}", column.to_column(), x),
+    )))
 }
 
-impl<'a> Index<'a> {
-    fn new(name: &'a str) -> Self {
-        Self {
-            unique: false,
-            name,
-            table: "",
-            columns: "".into(),
+fn single_column(sub: &Query) -> Result<Cow<'static, str>, Error> {
+    match (sub.select_columns.as_slice(), sub.select_exprs.is_empty()) {
+        ([column], true) => Ok(column.clone()),
+        _ => Err(Error::Sql("subquery must select exactly one column".into())),
+    }
+}
+
+// ands a bind free predicate onto the subquery's where
+fn and_where(mut sub: Query, predicate: String) -> Query {
+    sub.r#where = Some(
+        match sub
+            .r#where
+            .as_deref()
+            .and_then(|w| w.strip_prefix("where "))
+        {
+            Some(w) => format!("where ({}) and {}", w, predicate),
+            None => format!("where {}", predicate),
         }
+        .into(),
+    );
+    sub
+}
+
+// the select list of an exists subquery is never read
+fn select_one(mut sub: Query) -> Sql {
+    sub.select = SelectClause::Sql("select 1".into());
+    sub.select_columns.clear();
+    sub.select_exprs.clear();
+    sub.sql_statement::<usize>()
+}
+
+pub fn coalesce(columns: Vec<impl ToColumn>) -> Sql {
+    Sql {
+        clause: format!(
+            "coalesce({})",
+            columns
+                .iter()
+                .map(|c| c.to_column())
+                .collect::<Vec<_>>()
+                .join(",")
+        ),
+        params: vec![],
     }
+}
 
-    pub fn to_create_sql(&self) -> String {
-        format!(
-            "create {}index if not exists {} on {} ({});",
-            if self.unique { "unique " } else { "" },
-            self.name,
-            self.table,
-            self.columns
-        )
+pub fn ifnull(left: impl ToColumn, right: impl ToValueColumn) -> Sql {
+    Sql {
+        clause: format!("ifnull({}, {})", left.to_column(), right.to_placeholder()),
+        params: right.to_params(),
     }
+}
 
-    pub fn to_drop_sql(&self) -> String {
-        format!("drop index if exists {};", self.name)
+pub fn cast(col: impl ToColumn, ty: &str) -> Result<Sql, Error> {
+    match ty.to_lowercase().as_str() {
+        "integer" | "real" | "text" | "blob" | "numeric" => Ok(Sql {
+            clause: format!("cast({} as {})", col.to_column(), ty),
+            params: vec![],
+        }),
+        _ => Err(Error::Sql(format!(
+            "cannot cast to {}, expected integer, real, text, blob or numeric",
+            ty
+        ))),
     }
+}
 
-    pub fn unique(mut self) -> Self {
-        self.unique = true;
+fn function(name: &str, col: impl ToColumn) -> Sql {
+    Sql {
+        clause: format!("{}({})", name, col.to_column()),
+        params: vec![],
+    }
+}
+
+pub fn lower(col: impl ToColumn) -> Sql {
+    function("lower", col)
+}
+
+pub fn upper(col: impl ToColumn) -> Sql {
+    function("upper", col)
+}
+
+pub fn length(col: impl ToColumn) -> Sql {
+    function("length", col)
+}
+
+pub fn sum(col: impl ToColumn) -> Sql {
+    function("sum", col)
+}
+
+pub fn avg(col: impl ToColumn) -> Sql {
+    function("avg", col)
+}
+
+pub fn trim(col: impl ToColumn) -> Sql {
+    function("trim", col)
+}
+
+pub fn substr(col: impl ToColumn, start: i64, len: i64) -> Sql {
+    Sql {
+        clause: format!("substr({}, {}, {})", col.to_column(), start, len),
+        params: vec![],
+    }
+}
+
+pub fn datetime_now() -> Sql {
+    Sql {
+        clause: "datetime('now')".into(),
+        params: vec![],
+    }
+}
+
+pub fn date(col: impl ToColumn) -> Sql {
+    function("date", col)
+}
+
+pub fn datetime(col: impl ToColumn) -> Sql {
+    function("datetime", col)
+}
+
+pub fn julianday(col: impl ToColumn) -> Sql {
+    function("julianday", col)
+}
+
+pub fn strftime(fmt: &str, col: impl ToColumn) -> Sql {
+    Sql {
+        clause: format!(
+            "strftime('{}', {})",
+            fmt.replace('\'', "''"),
+            col.to_column()
+        ),
+        params: vec![],
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Case {
+    clause: String,
+    params: Vec<Value>,
+}
+
+pub fn case() -> Case {
+    Case::default()
+}
+
+impl Case {
+    pub fn when(mut self, predicate: Sql, then: impl ToValueColumn) -> Self {
+        self.clause.push_str(&format!(
+            " when {} then {}",
+            predicate.clause,
+            then.to_placeholder()
+        ));
+        self.params.extend(predicate.params);
+        self.params.extend(then.to_params());
+        self
+    }
+
+    pub fn otherwise(mut self, value: impl ToValueColumn) -> Self {
+        self.clause
+            .push_str(&format!(" else {}", value.to_placeholder()));
+        self.params.extend(value.to_params());
+        self
+    }
+
+    pub fn end(self) -> Sql {
+        Sql {
+            clause: format!("case{} end", self.clause),
+            params: self.params,
+        }
+    }
+
+    pub fn end_as(self, name: &'static str) -> SelectExpr {
+        self.end().as_(name)
+    }
+}
+
+pub trait Table {
+    fn new() -> Self
+    where
+        Self: Sized + Clone + Send + Sync;
+    fn table_name(&self) -> &'static str;
+    fn table_alias(&self) -> Option<&'static str>;
+    fn struct_name(&self) -> &'static str;
+    fn column_names(&self) -> Vec<&'static str>;
+    fn columns(&self) -> Vec<ColumnDef>;
+    /// The qualified `table.column` for a column or field name.
+    fn column(&self, name: &str) -> Option<&'static str>;
+    fn create_table_sql(&self) -> &'static str;
+    fn add_column_sql(&self, column_name: &str) -> String;
+
+    fn validate_row<R: Row>(&self) -> Result<(), Error>
+    where
+        Self: Sized,
+    {
+        let table_columns = self.column_names();
+        let row_columns = R::column_names();
+        let missing = table_columns
+            .iter()
+            .filter(|c| !row_columns.contains(c))
+            .copied()
+            .collect::<Vec<_>>();
+        let extra = row_columns
+            .iter()
+            .filter(|c| !table_columns.contains(c))
+            .copied()
+            .collect::<Vec<_>>();
+        if missing.is_empty() && extra.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::RowMismatch(format!(
+                "{}: missing ({}) extra ({})",
+                self.table_name(),
+                missing.join(","),
+                extra.join(",")
+            )))
+        }
+    }
+}
+
+/// Ties a row to the table it comes from, `#[table]` implements it for the row struct.
+pub trait HasTable: Row {
+    type Table: Table + Clone + Send + Sync;
+}
+
+/// A one to many relation from `#[ryzz(many = "Child(column)")]` on a parent field.
+pub trait HasMany<C: HasTable> {
+    /// `child.column = parent.field`
+    fn many_where(&self) -> Sql;
+}
+
+pub trait Row
+where
+    Self: DeserializeOwned + Send + Sync,
+{
+    fn column_names() -> Vec<&'static str>;
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("database connection closed")]
+    ConnectionClosed,
+    #[error("database connection closing: {0}")]
+    Close(String),
+    #[error("unique constraint failed: {0}")]
+    UniqueConstraint(String),
+    #[error("database error: {0}")]
+    TokioRusqlite(tokio_rusqlite::Error),
+    #[error("database error: {0}")]
+    Rusqlite(rusqlite::Error),
+    /// A failure reported by sqlite itself, `code` is the extended result code
+    /// like `SQLITE_CONSTRAINT_UNIQUE` (2067).
+    #[error("database error ({code}): {message}")]
+    Sqlite { code: i32, message: String },
+    #[error("missing from statement in sql query")]
+    MissingFrom,
+    #[error("error inserting record {0}")]
+    InsertError(String),
+    #[error("error converting value {0}")]
+    SqlConversion(String),
+    #[error("could not bind value: {0}")]
+    ToSql(String),
+    #[error("error building sql {0}")]
+    Sql(String),
+    #[error("could not find the row")]
+    RowNotFound,
+    #[error("invalid connection config: {0}")]
+    Config(String),
+    #[error("query interrupted")]
+    Interrupted,
+    #[error("row does not match table {0}")]
+    RowMismatch(String),
+    #[error("could not deserialize rows {0}")]
+    Deserialize(#[from] serde_json::Error),
+    #[error("serialize error: {0}")]
+    Serialize(#[from] serde_rusqlite::Error),
+}
+
+impl From<rusqlite::Error> for Error {
+    fn from(err: rusqlite::Error) -> Self {
+        match err {
+            rusqlite::Error::SqliteFailure(e, message) => Error::Sqlite {
+                code: e.extended_code,
+                message: message.unwrap_or_else(|| e.to_string()),
+            },
+            err => Error::Rusqlite(err),
+        }
+    }
+}
+
+impl From<tokio_rusqlite::Error> for Error {
+    fn from(err: tokio_rusqlite::Error) -> Self {
+        match err {
+            tokio_rusqlite::Error::Rusqlite(err) => err.into(),
+            err => Error::TokioRusqlite(err),
+        }
+    }
+}
+
+/// A window function call, `function over (partition by ... order by ...)`.
+#[derive(Debug)]
+pub struct Window {
+    function: Sql,
+    partition: Vec<Cow<'static, str>>,
+    order: Vec<Sql>,
+}
+
+pub fn row_number() -> Window {
+    window("row_number()")
+}
+
+pub fn rank() -> Window {
+    window("rank()")
+}
+
+pub fn dense_rank() -> Window {
+    window("dense_rank()")
+}
+
+fn window(function: &str) -> Window {
+    Window {
+        function: Sql {
+            clause: function.into(),
+            params: vec![],
+        },
+        partition: vec![],
+        order: vec![],
+    }
+}
+
+impl Window {
+    pub fn partition_by(mut self, columns: impl Select) -> Self {
+        self.partition.extend(columns.columns());
+        self
+    }
+
+    pub fn order_by(mut self, order: Vec<Sql>) -> Self {
+        self.order.extend(order);
+        self
+    }
+
+    pub fn as_(self, name: &'static str) -> SelectExpr {
+        Sql::from(self).as_(name)
+    }
+}
+
+impl From<Window> for Sql {
+    fn from(window: Window) -> Self {
+        let mut over = vec![];
+        if !window.partition.is_empty() {
+            over.push(format!("partition by {}", window.partition.join(",")));
+        }
+        if !window.order.is_empty() {
+            over.push(format!(
+                "order by {}",
+                window
+                    .order
+                    .iter()
+                    .map(|o| o.clause.as_str())
+                    .collect::<Vec<_>>()
+                    .join(",")
+            ));
+        }
+        let mut params = window.function.params;
+        params.extend(window.order.into_iter().flat_map(|o| o.params));
+        Sql {
+            clause: format!("{} over ({})", window.function.clause, over.join(" ")),
+            params,
+        }
+    }
+}
+
+// the wrapped library errors aren't comparable, so errors are equal when
+// they're the same variant with the same message
+impl PartialEq for Error {
+    fn eq(&self, other: &Self) -> bool {
+        std::mem::discriminant(self) == std::mem::discriminant(other)
+            && self.to_string() == other.to_string()
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Sql {
+    pub clause: String,
+    pub params: Vec<Value>,
+}
+
+impl Sql {
+    pub fn parts(&self) -> (&str, &[Value]) {
+        (&self.clause, &self.params)
+    }
+
+    /// Turns an aggregate like `sum(col)` into a window function, e.g. a running total
+    /// with `sum(col).over((), vec![asc(id)])`.
+    pub fn over(self, partition: impl Select, order: Vec<Sql>) -> Window {
+        Window {
+            function: self,
+            partition: vec![],
+            order: vec![],
+        }
+        .partition_by(partition)
+        .order_by(order)
+    }
+
+    pub fn as_(self, name: &'static str) -> SelectExpr {
+        SelectExpr {
+            name,
+            clause: self.clause,
+            params: self.params,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct SelectExpr {
+    pub name: &'static str,
+    pub clause: String,
+    pub params: Vec<Value>,
+}
+
+pub struct Index<'a> {
+    unique: bool,
+    name: Option<&'a str>,
+    table: &'a str,
+    columns: Vec<String>,
+    exprs: Vec<String>,
+    r#where: Option<String>,
+}
+
+impl<'a> Index<'a> {
+    fn new(name: Option<&'a str>) -> Self {
+        Self {
+            unique: false,
+            name,
+            table: "",
+            columns: vec![],
+            exprs: vec![],
+            r#where: None,
+        }
+    }
+
+    /// The explicit name or `{table}_{columns}_ix` with anything but letters and digits
+    /// squeezed into single underscores. Generated names over 63 bytes are
+    /// cut short and end in a hash of the full name, so they stay unique and stable.
+    pub fn name(&self) -> String {
+        if let Some(name) = self.name {
+            return name.to_string();
+        }
+        let name = std::iter::once(self.table)
+            .chain(self.columns.iter().map(|col| col.as_str()))
+            .chain(self.exprs.iter().map(|expr| expr.as_str()))
+            .chain(std::iter::once("ix"))
+            .collect::<Vec<_>>()
+            .join("_")
+            .split(|c: char| !c.is_alphanumeric())
+            .filter(|part| !part.is_empty())
+            .collect::<Vec<_>>()
+            .join("_");
+        if name.len() <= 63 {
+            return name;
+        }
+        // fnv-1a, std's hasher isn't guaranteed to give the same hash across releases
+        let hash = name.bytes().fold(0xcbf29ce484222325u64, |hash, byte| {
+            (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+        });
+        let mut end = 46;
+        while !name.is_char_boundary(end) {
+            end -= 1;
+        }
+        format!("{}_{:016x}", &name[..end], hash)
+    }
+
+    pub fn to_create_sql(&self) -> String {
+        format!(
+            "create {}index if not exists {} on {} ({}){};",
+            if self.unique { "unique " } else { "" },
+            quote_ident(&self.name()),
+            quote_ident(self.table),
+            self.columns
+                .iter()
+                .map(|col| quote_ident(col))
+                .chain(self.exprs.iter().cloned())
+                .collect::<Vec<_>>()
+                .join(","),
+            match &self.r#where {
+                Some(predicate) => format!(" where {}", predicate),
+                None => "".into(),
+            }
+        )
+    }
+
+    pub fn to_drop_sql(&self) -> String {
+        format!("drop index if exists {};", quote_ident(&self.name()))
+    }
+
+    pub fn unique(mut self) -> Self {
+        self.unique = true;
+
+        self
+    }
+
+    /// Indexes an expression like `lower(email)`, it goes into the sql as is after any columns.
+    pub fn expr(mut self, expr: &str) -> Self {
+        self.exprs.push(expr.to_string());
+        self
+    }
+
+    /// Makes a partial index over only the rows matching `sql`. Ddl can't take binds
+    /// so the params are written into the sql as literals, a nan or infinite real errors.
+    pub fn where_(mut self, sql: Sql) -> Result<Self, Error> {
+        self.r#where = Some(inline_params(&sql)?);
+        Ok(self)
+    }
+
+    pub fn on(mut self, table: impl Table, columns: impl Select) -> Self {
+        self.columns = columns
+            .columns()
+            .into_iter()
+            .map(|col| unqualify(&col))
+            .collect();
+        self.table = table.table_name();
+
+        self
+    }
+}
+
+// swaps each ? outside of a string literal or quoted identifier for its param as a sql literal
+fn inline_params(sql: &Sql) -> Result<String, Error> {
+    let mut params = sql.params.iter();
+    let mut inlined = String::with_capacity(sql.clause.len());
+    // the quote char of the literal or identifier we're in, a doubled quote toggles twice
+    let mut quote = None;
+    for c in sql.clause.chars() {
+        match c {
+            '\'' | '"' if quote.is_none() || quote == Some(c) => {
+                quote = match quote {
+                    Some(_) => None,
+                    None => Some(c),
+                };
+                inlined.push(c);
+            }
+            '?' if quote.is_none() => match params.next() {
+                Some(Value::Null) | None => inlined.push_str("null"),
+                Some(Value::Integer(i)) => inlined.push_str(&i.to_string()),
+                // sqlite has no literal for nan or infinity
+                Some(Value::Real(f)) if !f.is_finite() => {
+                    return Err(Error::Sql(format!(
+                        "{} can't be written as a sql literal",
+                        f
+                    )))
+                }
+                Some(Value::Real(f)) => inlined.push_str(&format!("{:?}", f)),
+                Some(Value::Text(s)) => inlined.push_str(&format!("'{}'", s.replace('\'', "''"))),
+                Some(Value::Blob(b)) => inlined.push_str(&format!(
+                    "x'{}'",
+                    b.iter()
+                        .map(|byte| format!("{:02x}", byte))
+                        .collect::<String>()
+                )),
+            },
+            c => inlined.push(c),
+        }
+    }
+    Ok(inlined)
+}
+
+// a savepoint nests inside an open transaction where begin would fail. rusqlite only rolls
+// a dropped savepoint back without releasing it, so errors release it here too
+fn in_savepoint<T>(
+    conn: &mut rusqlite::Connection,
+    f: impl FnOnce(&rusqlite::Connection) -> rusqlite::Result<T>,
+) -> rusqlite::Result<T> {
+    let mut savepoint = conn.savepoint()?;
+    match f(&savepoint) {
+        Ok(value) => {
+            savepoint.commit()?;
+            Ok(value)
+        }
+        Err(e) => {
+            savepoint.rollback()?;
+            savepoint.commit()?;
+            Err(e)
+        }
+    }
+}
+
+// sqlite's own parser finds the statement boundaries, comment only segments are skipped
+fn run_script(conn: &rusqlite::Connection, sql: &str) -> rusqlite::Result<Vec<usize>> {
+    let mut affected = vec![];
+    let mut batch = rusqlite::Batch::new(conn, sql);
+    while let Some(mut statement) = batch.next()? {
+        // SAFETY: the handle is a live connection, total_changes only reads a counter
+        let total_changes = || unsafe { rusqlite::ffi::sqlite3_total_changes64(conn.handle()) };
+        let before = total_changes();
+        statement.execute([])?;
+        affected.push((total_changes() - before) as usize);
+    }
+    Ok(affected)
+}
+
+pub fn index<'a>(name: &'a str) -> Index<'a> {
+    Index::new(Some(name))
+}
+
+/// An index named after its table and columns, see `Index::name`.
+pub fn index_on<'a>(table: impl Table, columns: impl Select) -> Index<'a> {
+    Index::new(None).on(table, columns)
+}
+
+/// The `create index` sql for an explicitly named index, `index(name).on(table, columns)`
+/// in one call. It takes the table too, the columns alone don't say which table they're on.
+pub fn create_index_named_sql(
+    name: &str,
+    unique: bool,
+    table: impl Table,
+    columns: impl Select,
+) -> String {
+    let index = index(name).on(table, columns);
+    match unique {
+        true => index.unique().to_create_sql(),
+        false => index.to_create_sql(),
+    }
+}
+
+/// A column as the table struct declares it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ColumnDef {
+    pub name: &'static str,
+    pub sql_type: &'static str,
+    pub primary_key: bool,
+    pub not_null: bool,
+    pub unique: bool,
+}
+
+/// A column as sqlite reports it in `pragma table_info`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ColumnInfo {
+    pub name: String,
+    pub sql_type: String,
+    pub not_null: bool,
+    pub primary_key: bool,
+}
+
+#[derive(Clone, Debug)]
+pub struct Database {
+    pub connection: tokio_rusqlite::Connection,
+    replica: Option<tokio_rusqlite::Connection>,
+    options: QueryOptions,
+}
+
+impl From<tokio_rusqlite::Connection> for Database {
+    fn from(connection: tokio_rusqlite::Connection) -> Self {
+        Self {
+            connection,
+            replica: None,
+            options: QueryOptions::default(),
+        }
+    }
+}
+
+impl Database {
+    pub async fn new(path: &str) -> Result<Self, Error> {
+        Ok(Connection::default(path).open().await?.into())
+    }
+
+    pub async fn with(connection: Connection) -> Result<Self, Error> {
+        Ok(connection.open().await?.into())
+    }
+
+    /// Opens `path` with the default pragmas, the same as `new`.
+    pub async fn open(path: &str) -> Result<Self, Error> {
+        Self::new(path).await
+    }
+
+    /// Opens a configured `Connection`, the same as `with`.
+    pub async fn open_with(connection: Connection) -> Result<Self, Error> {
+        Self::with(connection).await
+    }
+
+    /// Calls `f` after every query run through this database with its sql, timing and row count.
+    pub fn on_query(mut self, f: impl Fn(QueryEvent) + Send + Sync + 'static) -> Self {
+        self.options.on_query = Some(QueryHook(Arc::new(f)));
+        self
+    }
+
+    /// Sends `select` queries to `replica`, inserts, updates, deletes and migrations stay here.
+    pub fn with_replica(mut self, replica: Database) -> Self {
+        self.replica = Some(replica.connection);
+        self
+    }
+
+    fn query_builder(&self) -> Query<'_> {
+        let mut query = Query::new(&self.connection);
+        query.options = self.options.clone();
+        query
+    }
+
+    fn read_builder(&self) -> Query<'_> {
+        let mut query = Query::new(self.replica.as_ref().unwrap_or(&self.connection));
+        query.options = self.options.clone();
+        query
+    }
+
+    pub async fn execute_batch(&self, sql: &str) -> Result<(), Error> {
+        let sql: Arc<str> = sql.into();
+        self.connection
+            .call(move |conn| conn.execute_batch(&sql))
+            .await?;
+
+        Ok(())
+    }
+
+    /// Runs each statement in `sql` in order and returns the rows each one changed, 0 for ddl.
+    /// The script runs in a savepoint, when a statement fails none of them are kept.
+    /// Statements that return rows error, use `query` for those.
+    pub async fn execute_script(&self, sql: &str) -> Result<Vec<usize>, Error> {
+        observed(
+            &self.options,
+            sql,
+            |affected: &Vec<usize>| affected.iter().sum(),
+            retrying(&self.options, || self.execute_script_once(sql)),
+        )
+        .await
+    }
+
+    async fn execute_script_once(&self, sql: &str) -> Result<Vec<usize>, Error> {
+        let sql = sql.to_string();
+        let timeout = self.options.timeout;
+        let affected = self
+            .connection
+            .call(move |conn| {
+                with_timeout(conn, timeout, |conn| {
+                    conn.execute_batch("savepoint ryzz_script")?;
+                    match run_script(conn, &sql) {
+                        Ok(affected) => {
+                            conn.execute_batch("release ryzz_script")?;
+                            Ok(affected)
+                        }
+                        Err(err) => {
+                            conn.execute_batch("rollback to ryzz_script; release ryzz_script")?;
+                            Err(err)
+                        }
+                    }
+                })
+            })
+            .await
+            .map_err(call_error)?;
+
+        Ok(affected)
+    }
+
+    pub async fn execute(&self, sql: &str) -> Result<usize, Error> {
+        let sql = Sql {
+            clause: sql.into(),
+            params: vec![],
+        };
+        execute_with(&self.connection, sql, &self.options).await
+    }
+
+    /// Runs `sql` once per param set inside one savepoint, preparing it only once.
+    /// Inside an open transaction the rows only stick once that transaction commits.
+    pub async fn execute_many(
+        &self,
+        sql: &str,
+        param_sets: Vec<Vec<Value>>,
+    ) -> Result<usize, Error> {
+        let sql = sql.to_string();
+        let affected = self
+            .connection
+            .call(move |conn| {
+                in_savepoint(conn, |tx| {
+                    let mut stmt = tx.prepare(&sql)?;
+                    let mut affected = 0;
+                    for params in param_sets {
+                        affected += stmt.execute(params_from_iter(params))?;
+                    }
+                    Ok(affected)
+                })
+            })
+            .await?;
+
+        Ok(affected)
+    }
+
+    pub async fn execute_sql(&self, sql: Sql) -> Result<usize, Error> {
+        execute_with(&self.connection, sql, &self.options).await
+    }
+
+    pub async fn query<T: serde::de::DeserializeOwned + Send + 'static>(
+        &self,
+        sql: Sql,
+    ) -> Result<Vec<T>, Error> {
+        rows_with::<T>(&self.connection, sql, &self.options).await
+    }
+
+    pub fn select(&self, columns: impl Select) -> Query<'_> {
+        self.read_builder().select(columns)
+    }
+
+    /// Like `select` but always reads the primary, even with a replica set.
+    pub fn select_primary(&self, columns: impl Select) -> Query<'_> {
+        self.query_builder().select(columns)
+    }
+
+    pub fn insert(&self, table: impl Table) -> Query<'_> {
+        self.query_builder().insert(table)
+    }
+
+    pub fn insert_select(
+        &self,
+        table: impl Table,
+        columns: impl Select,
+        select: Query,
+    ) -> Query<'_> {
+        self.query_builder().insert_select(table, columns, select)
+    }
+
+    /// Reads a pragma's current value, e.g. `db.pragma_query::<String>("journal_mode")`.
+    pub async fn pragma_query<T: rusqlite::types::FromSql + Send + 'static>(
+        &self,
+        name: &str,
+    ) -> Result<T, Error> {
+        let name = name.to_string();
+        let value = self
+            .connection
+            .call(move |conn| conn.pragma_query_value(None, &name, |row| row.get(0)))
+            .await?;
+
+        Ok(value)
+    }
+
+    /// The database size in bytes, `page_count * page_size`.
+    pub async fn size_on_disk(&self) -> Result<u64, Error> {
+        let page_count: u64 = self.pragma_query("page_count").await?;
+        let page_size: u64 = self.pragma_query("page_size").await?;
+
+        Ok(page_count * page_size)
+    }
+
+    /// Copies the whole main database into bytes with `sqlite3_serialize`.
+    pub async fn serialize(&self) -> Result<Vec<u8>, Error> {
+        let bytes = self
+            .connection
+            .call(|conn| {
+                let mut size: i64 = 0;
+                // SAFETY: sqlite hands back a buffer of `size` bytes that we copy and then free
+                unsafe {
+                    let data = rusqlite::ffi::sqlite3_serialize(
+                        conn.handle(),
+                        c"main".as_ptr(),
+                        &mut size,
+                        0,
+                    );
+                    if data.is_null() {
+                        return Err(rusqlite::Error::SqliteFailure(
+                            rusqlite::ffi::Error::new(rusqlite::ffi::SQLITE_NOMEM),
+                            Some("could not serialize the database".into()),
+                        ));
+                    }
+                    let bytes = std::slice::from_raw_parts(data, size as usize).to_vec();
+                    rusqlite::ffi::sqlite3_free(data.cast());
+                    Ok(bytes)
+                }
+            })
+            .await?;
+
+        Ok(bytes)
+    }
+
+    /// Replaces the main database with `bytes` from `serialize`. The database then lives
+    /// in memory, even if this connection was opened on a file, which is left alone.
+    pub async fn deserialize(&self, bytes: Vec<u8>) -> Result<(), Error> {
+        self.connection
+            .call(move |conn| {
+                conn.flush_prepared_statement_cache();
+                let size = bytes.len() as i64;
+                // SAFETY: sqlite takes ownership of the sqlite3_malloc'd copy and frees it
+                // on close, or right away when deserializing fails
+                let rc = unsafe {
+                    let data =
+                        rusqlite::ffi::sqlite3_malloc64(bytes.len().max(1) as u64) as *mut u8;
+                    if data.is_null() {
+                        rusqlite::ffi::SQLITE_NOMEM
+                    } else {
+                        std::ptr::copy_nonoverlapping(bytes.as_ptr(), data, bytes.len());
+                        rusqlite::ffi::sqlite3_deserialize(
+                            conn.handle(),
+                            c"main".as_ptr(),
+                            data,
+                            size,
+                            size,
+                            (rusqlite::ffi::SQLITE_DESERIALIZE_FREEONCLOSE
+                                | rusqlite::ffi::SQLITE_DESERIALIZE_RESIZEABLE)
+                                as u32,
+                        )
+                    }
+                };
+                match rc {
+                    rusqlite::ffi::SQLITE_OK => Ok(()),
+                    rc => Err(rusqlite::Error::SqliteFailure(
+                        rusqlite::ffi::Error::new(rc),
+                        Some("could not deserialize the database".into()),
+                    )),
+                }
+            })
+            .await?;
+
+        Ok(())
+    }
+
+    /// A cheap `select 1` liveness check, any failure is `Error::ConnectionClosed`.
+    pub async fn ping(&self) -> Result<(), Error> {
+        self.connection
+            .call(|conn| conn.query_row("select 1", [], |_| Ok(())))
+            .await
+            .map_err(|_| Error::ConnectionClosed)
+    }
+
+    pub async fn table_exists(&self, name: &str) -> Result<bool, Error> {
+        let name = name.to_string();
+        let exists = self
+            .connection
+            .call(move |conn| {
+                conn.prepare_cached(
+                    "select 1 from sqlite_master where type = 'table' and name = ?",
+                )?
+                .exists([name])
+            })
+            .await?;
+
+        Ok(exists)
+    }
+
+    pub async fn column_exists(&self, table: &str, column: &str) -> Result<bool, Error> {
+        let (table, column) = (table.to_string(), column.to_string());
+        let exists = self
+            .connection
+            .call(move |conn| {
+                conn.prepare_cached("select 1 from pragma_table_info(?) where name = ?")?
+                    .exists([table, column])
+            })
+            .await?;
+
+        Ok(exists)
+    }
+
+    /// Every table in the schema except sqlite's own `sqlite_*` tables, sorted by name.
+    pub async fn list_tables(&self) -> Result<Vec<String>, Error> {
+        let tables = self
+            .connection
+            .call(|conn| {
+                conn.prepare_cached(
+                    "select name from sqlite_master where type = 'table' and name not like 'sqlite\\_%' escape '\\' order by name",
+                )?
+                .query_map((), |row| row.get(0))?
+                .collect::<rusqlite::Result<Vec<String>>>()
+            })
+            .await?;
+
+        Ok(tables)
+    }
+
+    pub async fn list_columns(&self, table: &str) -> Result<Vec<ColumnInfo>, Error> {
+        let table = table.to_string();
+        let columns = self
+            .connection
+            .call(move |conn| {
+                conn.prepare_cached(
+                    "select name, type, \"notnull\", pk from pragma_table_info(?) order by cid",
+                )?
+                .query_map([table], |row| {
+                    Ok(ColumnInfo {
+                        name: row.get(0)?,
+                        sql_type: row.get(1)?,
+                        not_null: row.get(2)?,
+                        primary_key: row.get::<_, i64>(3)? > 0,
+                    })
+                })?
+                .collect::<rusqlite::Result<Vec<_>>>()
+            })
+            .await?;
+
+        Ok(columns)
+    }
+
+    /// Deletes every row in `table` and resets its autoincrement counter, if it has one.
+    /// Runs in a savepoint, so it also works inside an open transaction.
+    pub async fn truncate(&self, table: impl Table) -> Result<usize, Error> {
+        let table_name = table.table_name();
+        let deleted = self
+            .connection
+            .call(move |conn| {
+                in_savepoint(conn, |tx| {
+                    let deleted =
+                        tx.execute(&format!("delete from {}", quote_ident(table_name)), ())?;
+                    let has_sequence = tx
+                        .prepare("select 1 from sqlite_master where type = 'table' and name = 'sqlite_sequence'")?
+                        .exists(())?;
+                    if has_sequence {
+                        tx.execute("delete from sqlite_sequence where name = ?", [table_name])?;
+                    }
+                    Ok(deleted)
+                })
+            })
+            .await?;
+
+        Ok(deleted)
+    }
+
+    pub fn delete_from(&self, table: impl Table) -> Query<'_> {
+        self.query_builder().delete(table)
+    }
+
+    pub fn insert_or(&self, table: impl Table, mode: OnConflict) -> Query<'_> {
+        self.query_builder().insert_or(table, mode)
+    }
+
+    pub fn upsert(
+        &self,
+        table: impl Table,
+        row: impl Serialize,
+        conflict: impl Select,
+    ) -> Result<Query<'_>, Error> {
+        self.query_builder().upsert(table, row, conflict)
+    }
+
+    pub fn delete_where_in(
+        &self,
+        table: impl Table,
+        column: impl ToColumn,
+        ids: Vec<impl ToValueColumn>,
+    ) -> Query<'_> {
+        self.query_builder().delete_where_in(table, column, ids)
+    }
+
+    pub fn update(&self, table: impl Table) -> Query<'_> {
+        self.query_builder().update(table)
+    }
+
+    /// `select ... from` the table of `T`, e.g. `db.find::<Post>().all::<Post>()`.
+    pub fn find<T: HasTable>(&self) -> Query<'_> {
+        self.select(()).from(T::Table::new())
+    }
+
+    /// The `C` rows that belong to `parent`, e.g. `db.many::<Comment>(&post).all::<Comment>()`.
+    pub fn many<C: HasTable>(&self, parent: &impl HasMany<C>) -> Query<'_> {
+        self.find::<C>().where_(parent.many_where())
+    }
+
+    pub async fn create<'a>(&'a self, index: &'a Index<'a>) -> Result<(), Error> {
+        let sql = index.to_create_sql();
+        let rows = self.execute(&sql).await?;
+        if rows > 0 {
+            println!("{}", sql);
+        }
+        Ok(())
+    }
+
+    pub async fn drop<'a>(&'a self, index: &'a Index<'a>) -> Result<(), Error> {
+        let sql = index.to_drop_sql();
+        let rows = self.execute(&sql).await?;
+        if rows > 0 {
+            println!("{}", sql);
+        }
+        Ok(())
+    }
+
+    pub async fn open_blob(
+        &self,
+        table: &str,
+        column: &str,
+        rowid: i64,
+        read_only: bool,
+    ) -> Result<BlobReader, Error> {
+        BlobReader::open(self.connection.clone(), table, column, rowid, read_only).await
+    }
+
+    /// Runs `f` inside `begin` / `commit`, rolling back instead when `f` returns an error.
+    pub async fn transaction<F, Fut, T>(&self, f: F) -> Result<T, Error>
+    where
+        F: FnOnce(Transaction) -> Fut,
+        Fut: Future<Output = Result<T, Error>>,
+    {
+        self.transaction_with(TransactionBehavior::Deferred, f)
+            .await
+    }
+
+    /// Like `transaction` but takes the write lock up front with `begin immediate`,
+    /// so concurrent writers wait at the start instead of failing with busy halfway through.
+    pub async fn transaction_immediate<F, Fut, T>(&self, f: F) -> Result<T, Error>
+    where
+        F: FnOnce(Transaction) -> Fut,
+        Fut: Future<Output = Result<T, Error>>,
+    {
+        self.transaction_with(TransactionBehavior::Immediate, f)
+            .await
+    }
+
+    /// Turns on extension loading until the returned guard is dropped.
+    ///
+    /// # Safety
+    ///
+    /// Extensions run arbitrary native code, see `rusqlite::Connection::load_extension_enable`.
+    #[cfg(feature = "load_extension")]
+    pub async unsafe fn enable_load_extension(&self) -> Result<ExtensionGuard, Error> {
+        self.connection
+            .call(|conn| unsafe { conn.load_extension_enable() })
+            .await?;
+        Ok(ExtensionGuard {
+            connection: self.connection.clone(),
+        })
+    }
+
+    /// Runs each migration not run before, in order, each in its own savepoint.
+    /// Migrations are remembered by file name in `ryzz_migrations`, along with their sql,
+    /// so editing a file that already ran doesn't run it again. Returns how many ran.
+    /// They run straight on the connection, the timeout, retry and `on_query` options don't apply.
+    pub async fn run_migrations(&self, migrations: &[SqlMigration]) -> Result<usize, Error> {
+        Migration::table(self).await?;
+        let migrations = migrations.to_vec();
+        let ran = self
+            .connection
+            .call(move |conn| {
+                let mut ran = 0;
+                for migration in migrations {
+                    ran += in_savepoint(conn, |tx| {
+                        let done = tx
+                            .prepare_cached("select 1 from ryzz_migrations where name = ?")?
+                            .exists([migration.name])?;
+                        if done {
+                            return Ok(0);
+                        }
+                        tx.execute_batch(migration.sql)?;
+                        tx.execute(
+                            "insert into ryzz_migrations (name, sql) values (?, ?)",
+                            [migration.name, migration.sql],
+                        )?;
+                        Ok(1)
+                    })?;
+                }
+                Ok(ran)
+            })
+            .await?;
+
+        Ok(ran)
+    }
+
+    /// Starts a transaction without a closure. Call `commit` to keep the changes,
+    /// dropping the `Tx` without committing rolls them back.
+    pub async fn begin(&self) -> Result<Tx, Error> {
+        self.execute_batch("begin;").await?;
+        Ok(Tx {
+            connection: self.connection.clone(),
+            options: self.options.clone(),
+            done: false,
+        })
+    }
+
+    /// Like `transaction` but begins with the given locking `behavior`.
+    pub async fn transaction_with<F, Fut, T>(
+        &self,
+        behavior: TransactionBehavior,
+        f: F,
+    ) -> Result<T, Error>
+    where
+        F: FnOnce(Transaction) -> Fut,
+        Fut: Future<Output = Result<T, Error>>,
+    {
+        let begin = match behavior {
+            TransactionBehavior::Deferred => "begin deferred;",
+            TransactionBehavior::Immediate => "begin immediate;",
+            TransactionBehavior::Exclusive => "begin exclusive;",
+        };
+        self.execute_batch(begin).await?;
+        let tx = Transaction {
+            connection: self.connection.clone(),
+            options: self.options.clone(),
+        };
+        match f(tx).await {
+            Ok(value) => {
+                self.execute_batch("commit;").await?;
+                Ok(value)
+            }
+            Err(err) => {
+                self.execute_batch("rollback;").await?;
+                Err(err)
+            }
+        }
+    }
+
+    pub async fn schema(&self) -> Result<String, Error> {
+        let schema_table = SqliteSchemaTable::new();
+        let rows = self
+            .query_builder()
+            .select(())
+            .from(schema_table)
+            .all::<SqliteSchema>()
+            .await?;
+        Ok(rows
+            .into_iter()
+            .map(|row| row.sql.to_lowercase())
+            .collect::<Vec<_>>()
+            .join("\n"))
+    }
+}
+
+type BlobRead = Pin<Box<dyn Future<Output = Result<Vec<u8>, tokio_rusqlite::Error>> + Send>>;
+
+// reads are fetched this many bytes at a time and handed out as the caller's buffer allows
+const BLOB_CHUNK: usize = 64 * 1024;
+
+// an open sqlite3_blob, only used on the connection's thread and closed on drop
+#[derive(Clone, Copy)]
+struct BlobHandle(*mut rusqlite::ffi::sqlite3_blob);
+
+// SAFETY: the handle is only dereferenced by sqlite inside `connection.call`,
+// which runs on the one thread that owns the connection
+unsafe impl Send for BlobHandle {}
+
+impl BlobHandle {
+    // a method so closures capture the whole Send handle, not just the raw pointer field
+    fn get(self) -> *mut rusqlite::ffi::sqlite3_blob {
+        self.0
+    }
+}
+
+fn blob_result(conn: &rusqlite::Connection, code: std::os::raw::c_int) -> rusqlite::Result<()> {
+    if code == rusqlite::ffi::SQLITE_OK {
+        return Ok(());
+    }
+    // SAFETY: the handle is a live connection and sqlite3_errmsg returns a nul terminated string
+    let message = unsafe { std::ffi::CStr::from_ptr(rusqlite::ffi::sqlite3_errmsg(conn.handle())) };
+    Err(rusqlite::Error::SqliteFailure(
+        rusqlite::ffi::Error::new(code),
+        Some(message.to_string_lossy().into_owned()),
+    ))
+}
+
+/// Reads a blob incrementally instead of loading the whole value into memory.
+/// The blob stays open until the reader is dropped.
+pub struct BlobReader {
+    connection: tokio_rusqlite::Connection,
+    blob: BlobHandle,
+    len: usize,
+    offset: usize,
+    unread: Vec<u8>,
+    pending: Option<BlobRead>,
+}
+
+impl BlobReader {
+    async fn open(
+        connection: tokio_rusqlite::Connection,
+        table: &str,
+        column: &str,
+        rowid: i64,
+        read_only: bool,
+    ) -> Result<Self, Error> {
+        let name = |s: &str| {
+            std::ffi::CString::new(s).map_err(|_| Error::Sql(format!("invalid name {:?}", s)))
+        };
+        let (table, column) = (name(table)?, name(column)?);
+        let (blob, len) = connection
+            .call(move |conn| {
+                let mut blob = std::ptr::null_mut();
+                // SAFETY: every pointer is valid for the call and sqlite writes the handle to `blob`
+                let code = unsafe {
+                    rusqlite::ffi::sqlite3_blob_open(
+                        conn.handle(),
+                        c"main".as_ptr(),
+                        table.as_ptr(),
+                        column.as_ptr(),
+                        rowid,
+                        !read_only as std::os::raw::c_int,
+                        &mut blob,
+                    )
+                };
+                blob_result(conn, code)?;
+                // SAFETY: blob was just opened
+                let len = unsafe { rusqlite::ffi::sqlite3_blob_bytes(blob) };
+                Ok((BlobHandle(blob), len as usize))
+            })
+            .await?;
+
+        Ok(Self {
+            connection,
+            blob,
+            len,
+            offset: 0,
+            unread: vec![],
+            pending: None,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Overwrites bytes starting at `offset`, blobs can't grow so write a zeroblob first.
+    pub async fn write_at(&self, bytes: Vec<u8>, offset: usize) -> Result<(), Error> {
+        if offset
+            .checked_add(bytes.len())
+            .map_or(true, |end| end > self.len)
+        {
+            return Err(Error::Sql(format!(
+                "write of {} bytes at {} is past the end of a {} byte blob",
+                bytes.len(),
+                offset,
+                self.len
+            )));
+        }
+        let blob = self.blob;
+        self.connection
+            .call(move |conn| {
+                // SAFETY: the range was checked against the blob size above
+                let code = unsafe {
+                    rusqlite::ffi::sqlite3_blob_write(
+                        blob.get(),
+                        bytes.as_ptr().cast(),
+                        bytes.len() as std::os::raw::c_int,
+                        offset as std::os::raw::c_int,
+                    )
+                };
+                blob_result(conn, code)
+            })
+            .await?;
+        Ok(())
+    }
+
+    fn read_chunk(&self) -> BlobRead {
+        let blob = self.blob;
+        let offset = self.offset;
+        let n = BLOB_CHUNK.min(self.len - offset);
+        let connection = self.connection.clone();
+        Box::pin(async move {
+            connection
+                .call(move |conn| {
+                    let mut chunk = vec![0u8; n];
+                    // SAFETY: the chunk has room for n bytes and offset + n is within the blob
+                    let code = unsafe {
+                        rusqlite::ffi::sqlite3_blob_read(
+                            blob.get(),
+                            chunk.as_mut_ptr().cast(),
+                            n as std::os::raw::c_int,
+                            offset as std::os::raw::c_int,
+                        )
+                    };
+                    blob_result(conn, code)?;
+                    Ok(chunk)
+                })
+                .await
+        })
+    }
+}
+
+impl AsyncRead for BlobReader {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        if self.unread.is_empty() {
+            if self.offset >= self.len {
+                return Poll::Ready(Ok(()));
+            }
+            if self.pending.is_none() {
+                self.pending = Some(self.read_chunk());
+            }
+            let Some(pending) = self.pending.as_mut() else {
+                return Poll::Ready(Ok(()));
+            };
+            let chunk = match pending.as_mut().poll(cx) {
+                Poll::Pending => return Poll::Pending,
+                Poll::Ready(result) => {
+                    self.pending = None;
+                    result.map_err(io::Error::other)?
+                }
+            };
+            self.offset += chunk.len();
+            self.unread = chunk;
+        }
+        // the caller's buffer can shrink between polls, keep what doesn't fit for the next one
+        let n = self.unread.len().min(buf.remaining());
+        buf.put_slice(&self.unread[..n]);
+        self.unread.drain(..n);
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl Drop for BlobReader {
+    fn drop(&mut self) {
+        let blob = self.blob;
+        // SAFETY: the handle is open and nothing uses it after this
+        let _ = block_on(self.connection.call(move |_| {
+            unsafe { rusqlite::ffi::sqlite3_blob_close(blob.get()) };
+            Ok(())
+        }));
+    }
+}
+
+// drop can't await, so cleanup waits here for the connection's thread to run it.
+// that thread isn't part of the async runtime so this can't deadlock it,
+// `unconstrained` keeps tokio's task budget from deferring the wakeup forever
+fn block_on<F: Future>(future: F) -> F::Output {
+    struct Unpark(std::thread::Thread);
+
+    impl std::task::Wake for Unpark {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    let waker = Waker::from(Arc::new(Unpark(std::thread::current())));
+    let mut cx = Context::from_waker(&waker);
+    let mut future = std::pin::pin!(tokio::task::coop::unconstrained(future));
+    loop {
+        match future.as_mut().poll(&mut cx) {
+            Poll::Ready(output) => return output,
+            Poll::Pending => std::thread::park(),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Transaction {
+    connection: tokio_rusqlite::Connection,
+    options: QueryOptions,
+}
+
+impl Transaction {
+    fn query_builder(&self) -> Query<'_> {
+        let mut query = Query::new(&self.connection);
+        query.options = self.options.clone();
+        query
+    }
+
+    pub fn select(&self, columns: impl Select) -> Query<'_> {
+        self.query_builder().select(columns)
+    }
+
+    pub fn insert(&self, table: impl Table) -> Query<'_> {
+        self.query_builder().insert(table)
+    }
+
+    pub fn update(&self, table: impl Table) -> Query<'_> {
+        self.query_builder().update(table)
+    }
+
+    pub fn delete_from(&self, table: impl Table) -> Query<'_> {
+        self.query_builder().delete(table)
+    }
+
+    pub async fn execute_batch(&self, sql: &str) -> Result<(), Error> {
+        let sql: Arc<str> = sql.into();
+        self.connection
+            .call(move |conn| conn.execute_batch(&sql))
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn execute(&self, sql: &str) -> Result<usize, Error> {
+        let sql: Arc<str> = sql.into();
+        let affected = self
+            .connection
+            .call(move |conn| conn.execute(&sql, ()))
+            .await?;
+
+        Ok(affected)
+    }
+
+    /// Runs `f` inside `savepoint "name"`, releasing the savepoint when `f` succeeds
+    /// and rolling back to it when `f` fails. The enclosing transaction stays open either way.
+    pub async fn savepoint<F, Fut, T>(&self, name: &str, f: F) -> Result<T, Error>
+    where
+        F: FnOnce(Transaction) -> Fut,
+        Fut: Future<Output = Result<T, Error>>,
+    {
+        let name = quote_ident(name);
+        self.execute_batch(&format!("savepoint {};", name)).await?;
+        match f(self.clone()).await {
+            Ok(value) => {
+                self.execute_batch(&format!("release savepoint {};", name))
+                    .await?;
+                Ok(value)
+            }
+            Err(err) => {
+                self.execute_batch(&format!(
+                    "rollback to savepoint {name}; release savepoint {name};"
+                ))
+                .await?;
+                Err(err)
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Tx {
+    connection: tokio_rusqlite::Connection,
+    options: QueryOptions,
+    done: bool,
+}
+
+impl Tx {
+    fn query_builder(&self) -> Query<'_> {
+        let mut query = Query::new(&self.connection);
+        query.options = self.options.clone();
+        query
+    }
+
+    async fn finish(&mut self, sql: &'static str) -> Result<(), Error> {
+        self.connection
+            .call(move |conn| conn.execute_batch(sql))
+            .await?;
+        self.done = true;
+        Ok(())
+    }
+
+    pub async fn commit(mut self) -> Result<(), Error> {
+        self.finish("commit;").await
+    }
+
+    pub async fn rollback(mut self) -> Result<(), Error> {
+        self.finish("rollback;").await
+    }
+
+    pub fn select(&self, columns: impl Select) -> Query<'_> {
+        self.query_builder().select(columns)
+    }
+
+    pub fn insert(&self, table: impl Table) -> Query<'_> {
+        self.query_builder().insert(table)
+    }
+
+    pub fn update(&self, table: impl Table) -> Query<'_> {
+        self.query_builder().update(table)
+    }
+
+    pub fn delete_from(&self, table: impl Table) -> Query<'_> {
+        self.query_builder().delete(table)
+    }
+}
+
+impl Drop for Tx {
+    // waits for the rollback so nothing sent after the drop runs inside the transaction
+    fn drop(&mut self) {
+        if self.done {
+            return;
+        }
+        let _ = block_on(self.connection.call(|conn| conn.execute_batch("rollback;")));
+    }
+}
+
+#[cfg(feature = "load_extension")]
+#[derive(Debug)]
+pub struct ExtensionGuard {
+    connection: tokio_rusqlite::Connection,
+}
+
+#[cfg(feature = "load_extension")]
+impl ExtensionGuard {
+    /// # Safety
+    ///
+    /// Runs the extension's native code, see `rusqlite::Connection::load_extension`.
+    pub async unsafe fn load(&self, path: &str, entry_point: Option<&str>) -> Result<(), Error> {
+        let path = path.to_string();
+        let entry_point = entry_point.map(|s| s.to_string());
+        self.connection
+            .call(move |conn| unsafe { conn.load_extension(path, entry_point.as_deref()) })
+            .await?;
+        Ok(())
+    }
+}
+
+#[cfg(feature = "load_extension")]
+impl Drop for ExtensionGuard {
+    // waits for loading to be off so nothing sent after the drop runs with it on
+    fn drop(&mut self) {
+        let _ = block_on(self.connection.call(|conn| conn.load_extension_disable()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[tokio::test]
+    #[allow(unused)]
+    async fn readme_works() -> Result<(), ryzz::Error> {
+        use ryzz::*;
+
+        #[table("posts")]
+        struct Post {
+            #[ryzz(pk)]
+            id: i64,
+            title: Option<String>,
+            body: String,
+        }
+
+        #[table]
+        struct Comment {
+            #[ryzz(pk)]
+            id: i64,
+            body: String,
+            #[ryzz(fk = "posts(id)")]
+            post_id: i64,
+        }
+
+        let db = Database::new(":memory:").await?;
+        let posts = Post::table(&db).await?;
+        let comments = Comment::table(&db).await?;
+
+        let post = Post {
+            id: 1,
+            title: None,
+            body: "".into(),
+        };
+
+        // insert into posts (id, body) values (?, ?) returning *
+        let mut post: Post = db.insert(posts).values(post)?.returning().await?;
+
+        assert_eq!(post.id, 1);
+        assert_eq!(post.body, "");
+        assert_eq!(post.title, None);
+
+        post.body = "post".into();
+
+        // update posts set body = ?, id = ? where id = ? returning *
+        let post: Post = db
+            .update(posts)
+            .set(post)?
+            .where_(and(eq(posts.id, 1), eq(posts.title, Value::Null)))
+            .returning()
+            .await?;
+
+        assert_eq!(post.id, 1);
+        assert_eq!(post.body, "post");
+
+        // delete from posts where id = ? returning *
+        let post: Post = db
+            .delete_from(posts)
+            .where_(eq(posts.id, 1))
+            .returning()
+            .await?;
+
+        assert_eq!(post.id, 1);
+        assert_eq!(post.body, "post");
+
+        let rows: Vec<Post> = db.select(()).from(posts).all().await?;
+
+        assert_eq!(rows.len(), 0);
+
+        let inserted: Post = db
+            .insert(posts)
+            .values(Post {
+                title: None,
+                id: 1,
+                body: "".into(),
+            })?
+            .returning()
+            .await?;
+
+        assert_eq!(rows.len(), 0);
+
+        let new_comment = Comment {
+            id: 1,
+            body: "".into(),
+            post_id: 1,
+        };
+
+        let comment: Comment = db
+            .insert(comments)
+            .values(new_comment.clone())?
+            .returning()
+            .await?;
+
+        let rows: Vec<Comment> = db.select(()).from(comments).all().await?;
+
+        assert_eq!(rows.len(), 1);
+        let row = &rows[0];
+        assert_eq!(row.id, new_comment.id);
+        assert_eq!(row.body, new_comment.body);
+        assert_eq!(row.post_id, new_comment.post_id);
+
+        let query = db
+            .select((comments.id, comments.body))
+            .from(comments)
+            .where_(eq(comments.id, 1))
+            .limit(1);
+
+        let rows: Vec<Comment> = query.all().await?;
+
+        assert_eq!(rows.len(), 1);
+
+        let comment = rows.into_iter().next().unwrap();
+        assert_eq!(comment.id, new_comment.id);
+        assert_eq!(comment.body, new_comment.body);
+        assert_eq!(comment.post_id, 0);
+
+        #[row]
+        struct CommentWithPost {
+            comment: Comment,
+            post: Post,
+        }
+
+        let rows: Vec<CommentWithPost> = db
+            .select(())
+            .from(comments)
+            .inner_join(posts, eq(posts.id, comments.post_id))
+            .all()
+            .await?;
+
+        assert_eq!(rows[0].comment.id, new_comment.id);
+        assert_eq!(rows[0].comment.body, new_comment.body);
+        assert_eq!(rows[0].comment.post_id, new_comment.post_id);
+        assert_eq!(rows[0].post.id, inserted.id);
+        assert_eq!(rows[0].post.title, inserted.title);
+        assert_eq!(rows[0].post.body, inserted.body);
+
+        let query = db.select(()).from(comments);
+
+        // prepare the query
+        let prepared = query.prep::<Comment>().await?;
+
+        // execute the prepared query later
+        let rows: Vec<Comment> = prepared.all().await?;
+
+        assert_eq!(rows[0].id, 1);
+        assert_eq!(rows[0].body, "");
+        assert_eq!(rows[0].post_id, 1);
+
+        Ok(())
+    }
+
+    #[allow(unused)]
+    #[tokio::test]
+    async fn indexes_works() -> Result<(), ryzz::Error> {
+        use ryzz::*;
+
+        #[table]
+        struct Link {
+            #[ryzz(pk)]
+            id: i64,
+            url: String,
+        }
+
+        let db = Database::new(":memory:").await?;
+        let links = Link::table(&db).await?;
+
+        let links_url_ix = index("links_url_ix").unique().on(links, links.url);
+
+        db.create(&links_url_ix).await?;
+
+        let rows = db
+            .insert(links)
+            .values(Link {
+                id: 1,
+                url: "".into(),
+            })?
+            .rows_affected()
+            .await?;
+
+        assert_eq!(rows, 1);
+
+        let result = db
+            .insert(links)
+            .values(Link {
+                id: 2,
+                url: "".into(),
+            })?
+            .rows_affected()
+            .await;
+
+        assert!(result.is_err());
+
+        db.drop(&links_url_ix).await?;
+
+        let result = db
+            .insert(links)
+            .values(Link {
+                id: 2,
+                url: "".into(),
+            })?
+            .rows_affected()
+            .await;
+
+        assert!(result.is_ok());
+
+        let rows = db.select(()).from(links).all::<Link>().await?;
+
+        assert_eq!(rows.len(), 2);
+
+        Ok(())
+    }
+
+    #[allow(unused)]
+    #[tokio::test]
+    async fn migrate_works() -> Result<(), ryzz::Error> {
+        use ryzz::*;
+
+        #[table("links")]
+        struct Link {
+            #[ryzz(pk)]
+            id: i64,
+            url: String,
+        }
+
+        let db = Database::new(":memory:").await?;
+        let links = Link::table(&db).await?;
+        let schema = db.schema().await?;
+
+        assert_eq!(
+            schema,
+            r#"create table "links" ("id" integer not null primary key,"url" text not null)"#
+        );
+
+        let links = Link::table(&db).await?;
+        let schema = db.schema().await?;
+
+        assert_eq!(
+            schema,
+            r#"create table "links" ("id" integer not null primary key,"url" text not null)"#
+        );
+
+        #[table("links")]
+        struct Link1 {
+            #[ryzz(pk)]
+            id: i64,
+            url: String,
+            test: Option<String>,
+        }
+
+        let links = Link1::table(&db).await?;
+        let schema = db.schema().await?;
+
+        assert_eq!(
+            schema,
+            r#"create table "links" ("id" integer not null primary key,"url" text not null, "test" text)"#
+        );
+
+        Ok(())
+    }
+
+    #[allow(unused)]
+    #[tokio::test]
+    async fn null_value_in_where_clause_works() -> Result<(), ryzz::Error> {
+        use ryzz::*;
+
+        #[table]
+        struct Chat {
+            #[ryzz(pk)]
+            id: i64,
+            msg: Option<String>,
+        }
+
+        let db = Database::new(":memory:").await?;
+        let chats = Chat::table(&db).await?;
+
+        let chat = Chat { id: 1, msg: None };
+        let chat2 = Chat {
+            id: 2,
+            msg: Some("".into()),
+        };
+
+        let chat: Chat = db.insert(chats).values(chat)?.returning().await?;
+        let chat2: Chat = db.insert(chats).values(chat2)?.returning().await?;
+
+        let rows: Vec<Chat> = db
+            .select(())
+            .from(chats)
+            .where_(eq(chats.msg, Value::Null))
+            .all()
+            .await?;
+
+        assert_eq!(rows.len(), 1);
+
+        let rows: Vec<Chat> = db
+            .select(())
+            .from(chats)
+            .where_(eq(chats.msg, "".to_string()))
+            .all()
+            .await?;
+
+        assert_eq!(rows.len(), 1);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn more_complicated_where_clause_works() -> Result<(), ryzz::Error> {
+        use ryzz::*;
+
+        #[table]
+        struct Glyph {
+            image: String,
+            aspect: i64,
+        }
+
+        let db = Database::new(":memory:").await?;
+        let glyphs = Glyph::table(&db).await?;
+
+        let query = db
+            .select(glyphs.image)
+            .from(glyphs)
+            .where_(or(in_(glyphs.aspect, vec![1, 2]), like(glyphs.image, "A%")));
+
+        let sql = query.sql_statement::<Glyph>();
+
+        assert_eq!(
+            r#"select json_object('image', "Glyph"."image")  from "Glyph" where ("Glyph"."aspect" in (?,?) or "Glyph"."image" like ?)"#,
+            sql.clause
+        );
+
+        assert_eq!(
+            vec![
+                Value::Integer(1),
+                Value::Integer(2),
+                Value::Text("A%".into())
+            ],
+            sql.params
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn default_works() -> Result<(), ryzz::Error> {
+        use ryzz::*;
+
+        #[table("defaults")]
+        struct Default {
+            #[ryzz(default_ = "'hello'")]
+            im_default: Option<String>,
+        }
+
+        let db = Database::new(":memory:").await?;
+        let defaults = Default::table(&db).await?;
+
+        assert_eq!(
+            r#"create table "defaults" ("im_default" text default 'hello')"#,
+            db.schema().await?
+        );
+
+        let query = db.insert(defaults).default_values();
+        let sql = query.sql_statement::<Default>();
+
+        assert_eq!(sql.clause, r#"insert into "defaults" default values"#);
+        assert_eq!(sql.params, vec![]);
+
+        let row: Default = query.returning().await?;
+
+        assert_eq!(row.im_default, Some("hello".into()));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn savepoint_works() -> Result<(), ryzz::Error> {
+        use ryzz::*;
+
+        #[table("items")]
+        struct Item {
+            #[ryzz(pk)]
+            id: i64,
+        }
+
+        let db = Database::new(":memory:").await?;
+        let items = Item::table(&db).await?;
+
+        db.transaction(|tx| async move {
+            tx.execute("insert into items (id) values (1)").await?;
+            let inner = tx
+                .savepoint("inner; drop table items", |sp| async move {
+                    sp.execute("insert into items (id) values (2)").await?;
+                    Err::<(), Error>(Error::Sql("rollback inner".into()))
+                })
+                .await;
+            assert!(inner.is_err());
+            tx.execute("insert into items (id) values (3)").await?;
+            Ok(())
+        })
+        .await?;
+
+        let rows: Vec<Item> = db.select(()).from(items).all().await?;
+        let ids = rows.iter().map(|row| row.id).collect::<Vec<_>>();
+
+        assert_eq!(ids, vec![1, 3]);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn limit_param_works() -> Result<(), ryzz::Error> {
+        use ryzz::*;
+
+        #[table]
+        struct Glyph {
+            image: String,
+            aspect: i64,
+        }
+
+        let db = Database::new(":memory:").await?;
+        let glyphs = Glyph::table(&db).await?;
+
+        let query = db
+            .select(glyphs.image)
+            .from(glyphs)
+            .limit_param(2)
+            .where_(eq(glyphs.aspect, 1));
+
+        let sql = query.sql_statement::<Glyph>();
+
+        assert_eq!(
+            r#"select json_object('image', "Glyph"."image")  from "Glyph" where "Glyph"."aspect" = ? limit ?"#,
+            sql.clause
+        );
+        assert_eq!(vec![Value::Integer(1), Value::Integer(2)], sql.params);
+
+        let rows: Vec<Glyph> = query.all().await?;
+
+        assert_eq!(rows.len(), 0);
+
+        Ok(())
+    }
+
+    #[allow(unused)]
+    #[test]
+    fn case_works() {
+        use ryzz::*;
+
+        #[table]
+        struct Glyph {
+            image: String,
+            aspect: i64,
+        }
+
+        let glyphs = GlyphTable::new();
+        let sql = case()
+            .when(eq(glyphs.aspect, 1), "square")
+            .when(gt(glyphs.aspect, 1), "wide")
+            .otherwise(glyphs.image)
+            .end();
+
+        assert_eq!(
+            r#"case when "Glyph"."aspect" = ? then ? when "Glyph"."aspect" > ? then ? else "Glyph"."image" end"#,
+            sql.clause
+        );
+        assert_eq!(
+            vec![
+                Value::Integer(1),
+                Value::Text("square".into()),
+                Value::Integer(1),
+                Value::Text("wide".into())
+            ],
+            sql.params
+        );
+    }
+
+    #[tokio::test]
+    async fn select_expr_works() -> Result<(), ryzz::Error> {
+        use ryzz::*;
+
+        #[table]
+        struct Glyph {
+            image: String,
+            aspect: i64,
+        }
+
+        #[row]
+        struct GlyphShape {
+            image: String,
+            shape: String,
+        }
+
+        let db = Database::new(":memory:").await?;
+        let glyphs = Glyph::table(&db).await?;
+
+        db.insert(glyphs)
+            .values(Glyph {
+                image: "a".into(),
+                aspect: 1,
+            })?
+            .rows_affected()
+            .await?;
+
+        let query = db
+            .select(glyphs.image)
+            .from(glyphs)
+            .select_expr(
+                case()
+                    .when(eq(glyphs.aspect, 1), "square")
+                    .otherwise("wide")
+                    .end_as("shape"),
+            )
+            .where_(eq(glyphs.image, "a"));
+
+        let sql = query.sql_statement::<GlyphShape>();
+
+        assert_eq!(
+            r#"select json_object('image', "Glyph"."image",'shape', case when "Glyph"."aspect" = ? then ? else ? end)  from "Glyph" where "Glyph"."image" = ?"#,
+            sql.clause
+        );
+        assert_eq!(
+            vec![
+                Value::Integer(1),
+                Value::Text("square".into()),
+                Value::Text("wide".into()),
+                Value::Text("a".into())
+            ],
+            sql.params
+        );
+
+        let rows: Vec<GlyphShape> = query.all().await?;
+
+        assert_eq!(rows[0].image, "a");
+        assert_eq!(rows[0].shape, "square");
+
+        Ok(())
+    }
+
+    #[allow(unused)]
+    #[test]
+    fn coalesce_and_ifnull_work() {
+        use ryzz::*;
+
+        #[table]
+        struct Profile {
+            nickname: Option<String>,
+            name: String,
+        }
+
+        let profiles = ProfileTable::new();
+
+        let sql = coalesce(vec![profiles.nickname.0, profiles.name]);
+        assert_eq!(
+            r#"coalesce("Profile"."nickname","Profile"."name")"#,
+            sql.clause
+        );
+        assert!(sql.params.is_empty());
+
+        let sql = ifnull(profiles.nickname, "anonymous");
+        assert_eq!(r#"ifnull("Profile"."nickname", ?)"#, sql.clause);
+        assert_eq!(vec![Value::Text("anonymous".into())], sql.params);
+    }
+
+    #[allow(unused)]
+    #[test]
+    fn cast_works() -> Result<(), ryzz::Error> {
+        use ryzz::*;
+
+        #[table("accounts")]
+        struct Account {
+            #[ryzz(pk)]
+            id: i64,
+        }
+
+        let accounts = AccountTable::new();
+
+        let sql = cast(accounts.id, "text")?;
+        assert_eq!(r#"cast("accounts"."id" as text)"#, sql.clause);
+
+        assert!(cast(accounts.id, "varchar; drop table accounts").is_err());
+
+        Ok(())
+    }
+
+    #[allow(unused)]
+    #[test]
+    fn string_functions_work() {
+        use ryzz::*;
+
+        #[table("accounts")]
+        struct Account {
+            name: String,
+        }
+
+        let accounts = AccountTable::new();
+
+        assert_eq!(r#"lower("accounts"."name")"#, lower(accounts.name).clause);
+        assert_eq!(r#"upper("accounts"."name")"#, upper(accounts.name).clause);
+        assert_eq!(r#"length("accounts"."name")"#, length(accounts.name).clause);
+        assert_eq!(r#"trim("accounts"."name")"#, trim(accounts.name).clause);
+        assert_eq!(
+            r#"substr("accounts"."name", 1, 3)"#,
+            substr(accounts.name, 1, 3).clause
+        );
+    }
+
+    #[tokio::test]
+    async fn date_functions_work() -> Result<(), ryzz::Error> {
+        use ryzz::*;
+
+        #[table("events")]
+        struct Event {
+            at: String,
+        }
+
+        #[row]
+        struct EventYear {
+            year: String,
+        }
+
+        let db = Database::new(":memory:").await?;
+        let events = Event::table(&db).await?;
+
+        assert_eq!("datetime('now')", datetime_now().clause);
+        assert_eq!(r#"date("events"."at")"#, date(events.at).clause);
+        assert_eq!(r#"julianday("events"."at")"#, julianday(events.at).clause);
+        assert_eq!(
+            r#"strftime('%Y', "events"."at")"#,
+            strftime("%Y", events.at).clause
+        );
+
+        db.insert(events)
+            .values(Event {
+                at: "2024-01-02 03:04:05".into(),
+            })?
+            .rows_affected()
+            .await?;
+
+        let rows: Vec<EventYear> = db
+            .select(())
+            .from(events)
+            .select_expr(strftime("%Y", events.at).as_("year"))
+            .all()
+            .await?;
+
+        assert_eq!(rows[0].year, "2024");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn default_expr_works() -> Result<(), ryzz::Error> {
+        use ryzz::*;
+
+        #[table("stamps")]
+        struct Stamp {
+            #[ryzz(default_ = "'hello'")]
+            literal: Option<String>,
+            #[ryzz(default_expr = "current_timestamp")]
+            created_at: Option<String>,
+            #[ryzz(default_expr = "datetime('now')")]
+            updated_at: Option<String>,
+        }
+
+        let db = Database::new(":memory:").await?;
+        let stamps = Stamp::table(&db).await?;
+
+        assert_eq!(
+            r#"create table if not exists "stamps" ("literal" Text default 'hello',"created_at" Text default current_timestamp,"updated_at" Text default (datetime('now')));"#,
+            stamps.create_table_sql()
+        );
+
+        let row: Stamp = db.insert(stamps).default_values().returning().await?;
+
+        assert_eq!(row.literal, Some("hello".into()));
+        assert!(row.created_at.is_some());
+        assert_eq!(row.created_at, row.updated_at);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn default_literal_works() -> Result<(), ryzz::Error> {
+        use ryzz::*;
+
+        #[table("members")]
+        struct Member {
+            #[ryzz(default_ = "active")]
+            status: String,
+            #[ryzz(default_ = "0")]
+            visits: i64,
+            #[ryzz(default_ = "'quoted'")]
+            note: String,
+            #[ryzz(default_ = "(datetime('now'))")]
+            created_at: String,
+            #[ryzz(default_ = "pending (review)")]
+            review: String,
+            #[ryzz(default_ = "(a) (b)")]
+            tags: String,
+        }
+
+        let db = Database::new(":memory:").await?;
+        let members = Member::table(&db).await?;
+
+        assert_eq!(
+            r#"create table if not exists "members" ("status" Text not null default 'active',"visits" Integer not null default 0,"note" Text not null default 'quoted',"created_at" Text not null default (datetime('now')),"review" Text not null default 'pending (review)',"tags" Text not null default '(a) (b)');"#,
+            members.create_table_sql()
+        );
+
+        let row: Member = db.insert(members).default_values().returning().await?;
+
+        assert_eq!(row.status, "active");
+        assert_eq!(row.visits, 0);
+        assert_eq!(row.note, "quoted");
+        assert_eq!(row.created_at.len(), "2000-01-01 00:00:00".len());
+        assert_eq!(row.review, "pending (review)");
+        assert_eq!(row.tags, "(a) (b)");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn renamed_column_works() -> Result<(), ryzz::Error> {
+        use ryzz::*;
+
+        #[table("people")]
+        struct Person {
+            #[ryzz(pk)]
+            id: i64,
+            #[ryzz(column = "full_name")]
+            name: String,
+        }
+
+        let db = Database::new(":memory:").await?;
+        let people = Person::table(&db).await?;
+
+        assert_eq!(
+            r#"create table if not exists "people" ("id" Integer not null primary key,"full_name" Text not null);"#,
+            people.create_table_sql()
+        );
+
+        let inserted: Person = db
+            .insert(people)
+            .values(Person {
+                id: 1,
+                name: "Ada".into(),
+            })?
+            .returning()
+            .await?;
+
+        assert_eq!(inserted.name, "Ada");
+
+        let rows: Vec<Person> = db
+            .select(())
+            .from(people)
+            .where_(eq(people.name, "Ada"))
+            .all()
+            .await?;
+
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].name, "Ada");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn skipped_field_works() -> Result<(), ryzz::Error> {
+        use ryzz::*;
+
+        #[table("notes")]
+        struct Note {
+            #[ryzz(pk)]
+            id: i64,
+            body: String,
+            #[ryzz(skip)]
+            preview: Vec<String>,
+        }
+
+        let db = Database::new(":memory:").await?;
+        let notes = Note::table(&db).await?;
+
+        assert_eq!(
+            r#"create table if not exists "notes" ("id" Integer not null primary key,"body" Text not null);"#,
+            notes.create_table_sql()
+        );
+        assert_eq!(vec!["id", "body"], Note::column_names());
+
+        let query = db.insert(notes).values(Note {
+            id: 1,
+            body: "hello".into(),
+            preview: vec!["he".into()],
+        })?;
+
+        assert_eq!(
+            r#"insert into "notes" ("id","body") values (?,?)"#,
+            query.sql_statement::<Note>().clause
+        );
+
+        let note: Note = query.returning().await?;
+
+        assert_eq!(note.body, "hello");
+        assert!(note.preview.is_empty());
+
+        Ok(())
+    }
+
+    #[allow(unused)]
+    #[test]
+    fn validate_row_works() {
+        use ryzz::*;
+
+        #[table("accounts")]
+        struct Account {
+            #[ryzz(pk)]
+            id: i64,
+            name: String,
+        }
+
+        #[row]
+        struct AccountName {
+            id: i64,
+            nickname: String,
+        }
+
+        let accounts = AccountTable::new();
+
+        assert!(accounts.validate_row::<Account>().is_ok());
+
+        let err = accounts.validate_row::<AccountName>().unwrap_err();
+        assert_eq!(
+            "row does not match table accounts: missing (name) extra (nickname)",
+            err.to_string()
+        );
+    }
+
+    #[tokio::test]
+    async fn from_tables_works() -> Result<(), ryzz::Error> {
+        use ryzz::*;
+
+        #[table("accounts")]
+        struct Account {
+            #[ryzz(pk)]
+            id: i64,
+            name: String,
+        }
+
+        #[table("posts")]
+        struct Post {
+            #[ryzz(pk)]
+            id: i64,
+            account_id: i64,
+        }
+
+        #[row]
+        struct AccountPost {
+            account: Account,
+            post: Post,
+        }
+
+        let db = Database::new(":memory:").await?;
+        let accounts = Account::table(&db).await?;
+        let posts = Post::table(&db).await?;
+
+        db.insert(accounts)
+            .values(Account {
+                id: 1,
+                name: "a".into(),
+            })?
+            .rows_affected()
+            .await?;
+        db.insert(posts)
+            .values(Post {
+                id: 2,
+                account_id: 1,
+            })?
+            .rows_affected()
+            .await?;
+
+        let query = db
+            .select(())
+            .from_tables(vec![&accounts, &posts])
+            .where_(eq(posts.account_id, accounts.id));
+
+        assert_eq!(
+            r#"select json_object('account', json_object('id', "accounts"."id",'name', "accounts"."name") ,'post', json_object('id', "posts"."id",'account_id', "posts"."account_id") ) from "accounts", "posts" where "posts"."account_id" = "accounts"."id""#,
+            query.sql_statement::<AccountPost>().clause
+        );
+
+        let rows: Vec<AccountPost> = query.all().await?;
+
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].account.name, "a");
+        assert_eq!(rows[0].post.id, 2);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn prepare_insert_works() -> Result<(), ryzz::Error> {
+        use ryzz::*;
+
+        #[table("events")]
+        struct Event {
+            #[ryzz(pk)]
+            id: i64,
+            name: String,
+        }
+
+        let db = Database::new(":memory:").await?;
+        let events = Event::table(&db).await?;
+
+        let insert = db.insert(events).prepare_insert().await?;
+
+        assert_eq!(
+            r#"insert into "events" ("id","name") values (:id,:name)"#,
+            insert.sql()
+        );
+
+        for id in 0..1000 {
+            let affected = insert
+                .execute(Event {
+                    id,
+                    name: format!("event {}", id),
+                })
+                .await?;
+            assert_eq!(affected, 1);
+        }
+
+        let rows: Vec<Event> = db.select(()).from(events).all().await?;
+
+        assert_eq!(rows.len(), 1000);
+        assert_eq!(rows[999].name, "event 999");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn statement_cache_capacity_works() -> Result<(), ryzz::Error> {
+        use ryzz::*;
+
+        #[table("events")]
+        struct Event {
+            #[ryzz(pk)]
+            id: i64,
+        }
+
+        // 20 distinct cached statements, more than rusqlite's default capacity of 16
+        async fn prepare_20(db: &Database) -> Result<(), ryzz::Error> {
+            let events = Event::table(db).await?;
+            for id in 0..20 {
+                let rows: Vec<Event> = db
+                    .select(())
+                    .from(events)
+                    .where_(eq(events.id, id))
+                    .limit(id)
+                    .prep::<Event>()
+                    .await?
+                    .all()
+                    .await?;
+                assert!(rows.is_empty());
+            }
+            Ok(())
+        }
+
+        let db = Database::new(":memory:").await?;
+        prepare_20(&db).await?;
+        assert_eq!(live_statements(&db).await?, 16);
+
+        let db =
+            Database::with(Connection::default(":memory:").statement_cache_capacity(32)).await?;
+        prepare_20(&db).await?;
+        assert_eq!(live_statements(&db).await?, 20);
+
+        let db =
+            Database::with(Connection::default(":memory:").statement_cache_capacity(4)).await?;
+        prepare_20(&db).await?;
+        assert_eq!(live_statements(&db).await?, 4);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn with_timeout_works() -> Result<(), ryzz::Error> {
+        use ryzz::*;
+        use std::time::Duration;
+
+        #[table("events")]
+        struct Event {
+            #[ryzz(pk)]
+            id: i64,
+        }
+
+        let db = Database::new(":memory:").await?;
+        let events = Event::table(&db).await?;
+
+        db.insert(events)
+            .values(Event { id: 1 })?
+            .rows_affected()
+            .await?;
+
+        let slow = Sql {
+            clause: "(with recursive c(x) as (select 1 union all select x + 1 from c) select count(*) from c where x < 1000000000) > 0".into(),
+            params: vec![],
+        };
+
+        let result = db
+            .select(())
+            .from(events)
+            .where_(slow)
+            .with_timeout(Duration::from_millis(50))
+            .all::<Event>()
+            .await;
+
+        assert!(matches!(result, Err(Error::Interrupted)));
+
+        let rows: Vec<Event> = db
+            .select(())
+            .from(events)
+            .with_timeout(Duration::from_secs(5))
+            .all()
+            .await?;
+
+        assert_eq!(rows.len(), 1);
+
+        // the timeout goes away with its query, a slower one after it runs to the end
+        db.select(())
+            .from(events)
+            .with_timeout(Duration::from_millis(20))
+            .all::<Event>()
+            .await?;
+        let rows: Vec<Event> = db
+            .select(())
+            .from(events)
+            .where_(Sql {
+                clause: "(with recursive c(x) as (select 1 union all select x + 1 from c where x < 1000000) select count(*) from c) > 0".into(),
+                params: vec![],
+            })
+            .all()
+            .await?;
+
+        assert_eq!(rows.len(), 1);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn open_blob_works() -> Result<(), ryzz::Error> {
+        use ryzz::*;
+        use tokio::io::AsyncReadExt;
+
+        let db = Database::new(":memory:").await?;
+        db.execute("create table files (id integer primary key, data blob not null)")
+            .await?;
+        db.execute("insert into files (id, data) values (1, zeroblob(10000))")
+            .await?;
+
+        let bytes = (0..10000).map(|i| (i % 251) as u8).collect::<Vec<_>>();
+        let writer = db.open_blob("files", "data", 1, false).await?;
+        writer.write_at(bytes.clone(), 0).await?;
+
+        let mut reader = db.open_blob("files", "data", 1, true).await?;
+        assert!(db.open_blob("files", "data", 2, true).await.is_err());
+        assert_eq!(reader.len(), 10000);
+
+        let mut read = vec![];
+        let mut chunk = [0u8; 4096];
+        // the buffer shrinks between reads, which AsyncRead allows
+        for size in [4096, 7, 1].into_iter().cycle() {
+            let n = reader.read(&mut chunk[..size]).await.unwrap();
+            if n == 0 {
+                break;
+            }
+            read.extend_from_slice(&chunk[..n]);
+        }
+
+        assert_eq!(read, bytes);
+        assert!(writer.write_at(vec![0; 2], 9999).await.is_err());
+        assert!(writer.write_at(vec![0; 2], usize::MAX).await.is_err());
+        assert!(!writer.is_empty());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn columns_macro_works() -> Result<(), ryzz::Error> {
+        use ryzz::*;
+
+        #[table("accounts")]
+        struct Account {
+            #[ryzz(pk)]
+            id: i64,
+            name: String,
+            email: String,
+        }
+
+        #[row]
+        struct AccountName {
+            id: i64,
+            name: String,
+        }
+
+        let db = Database::new(":memory:").await?;
+        let accounts = Account::table(&db).await?;
+
+        let cols = columns!(accounts.id, accounts.name);
+        assert_eq!(r#""accounts"."id", "accounts"."name""#, cols.column_list());
+
+        let query = db.select(cols).from(accounts);
+        assert_eq!(
+            r#"select json_object('id', "accounts"."id",'name', "accounts"."name")  from "accounts""#,
+            query.sql_statement::<AccountName>().clause
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn error_partial_eq_works() -> Result<(), ryzz::Error> {
+        use ryzz::*;
+
+        #[table("accounts")]
+        struct Account {
+            #[ryzz(pk)]
+            id: i64,
+        }
+
+        let db = Database::new(":memory:").await?;
+        let accounts = Account::table(&db).await?;
+
+        let err = db
+            .select(())
+            .from(accounts)
+            .first::<Account>()
+            .await
+            .unwrap_err();
+
+        assert_eq!(err, Error::RowNotFound);
+        assert_eq!(Error::Sql("a".into()), Error::Sql("a".into()));
+        assert_ne!(Error::Sql("a".into()), Error::Sql("b".into()));
+        assert_ne!(Error::Sql("a".into()), Error::SqlConversion("a".into()));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn retry_works() -> Result<(), ryzz::Error> {
+        use ryzz::*;
+        use std::time::Duration;
+
+        #[table("events")]
+        struct Event {
+            #[ryzz(pk)]
+            id: i64,
+        }
+
+        let path = std::env::temp_dir().join(format!("ryzz-retry-{}.sqlite3", std::process::id()));
+        let path = path.to_str().unwrap();
+        let _ = std::fs::remove_file(path);
+
+        let locker = Database::new(path).await?;
+        let events = Event::table(&locker).await?;
+        let db = Database::with(Connection::default(path).pragma("busy_timeout = 0")).await?;
+
+        locker.execute("begin exclusive").await?;
+
+        let err = db
+            .insert(events)
+            .values(Event { id: 1 })?
+            .retry(2)
+            .rows_affected()
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("locked"));
+
+        let commit = locker.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            commit.execute("commit").await.unwrap();
+        });
+
+        let affected = db
+            .insert(events)
+            .values(Event { id: 1 })?
+            .retry(10)
+            .rows_affected()
+            .await?;
+        assert_eq!(affected, 1);
+
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = std::fs::remove_file(format!("{path}{suffix}"));
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn on_query_works() -> Result<(), ryzz::Error> {
+        use ryzz::*;
+        use std::sync::{Arc, Mutex};
+
+        #[table("events")]
+        struct Event {
+            #[ryzz(pk)]
+            id: i64,
+        }
+
+        let events_seen = Arc::new(Mutex::new(vec![]));
+        let seen = events_seen.clone();
+        let db = Database::new(":memory:")
+            .await?
+            .on_query(move |event| seen.lock().unwrap().push(event));
+        let events = Event::table(&db).await?;
+        events_seen.lock().unwrap().clear();
+
+        db.insert(events)
+            .values(Event { id: 1 })?
+            .rows_affected()
+            .await?;
+        let rows: Vec<Event> = db.select(()).from(events).all().await?;
+        assert_eq!(rows.len(), 1);
+
+        {
+            let seen = events_seen.lock().unwrap();
+            assert_eq!(seen.len(), 2);
+            assert_eq!(seen[0].kind, QueryKind::Insert);
+            assert_eq!(seen[0].rows, 1);
+            assert_eq!(seen[1].kind, QueryKind::Select);
+            assert_eq!(seen[1].rows, 1);
+            assert!(seen[1].sql.starts_with("select"));
+        }
+
+        // prepared handles go through the hook too
+        events_seen.lock().unwrap().clear();
+        let insert = db.insert(events).prepare_insert().await?;
+        insert.execute(Event { id: 2 }).await?;
+        let by_id = db
+            .select(())
+            .from(events)
+            .where_(eq(events.id, Value::Integer(0)))
+            .prepare::<Event>();
+        by_id.first(vec![Value::Integer(2)]).await?;
+
+        let seen = events_seen.lock().unwrap();
+        assert_eq!(seen.len(), 2);
+        assert_eq!(seen[0].kind, QueryKind::Insert);
+        assert_eq!(seen[0].rows, 1);
+        assert_eq!(seen[1].kind, QueryKind::Select);
+        assert_eq!(seen[1].rows, 1);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn clone_for_count_works() -> Result<(), ryzz::Error> {
+        use ryzz::*;
+
+        #[table("events")]
+        struct Event {
+            #[ryzz(pk)]
+            id: i64,
+            kind: String,
+        }
+
+        #[row]
+        struct Total {
+            count: i64,
+        }
+
+        let db = Database::new(":memory:").await?;
+        let events = Event::table(&db).await?;
+
+        for id in 1..=5 {
+            db.insert(events)
+                .values(Event {
+                    id,
+                    kind: if id % 2 == 0 { "even" } else { "odd" }.into(),
+                })?
+                .rows_affected()
+                .await?;
+        }
+
+        let page = db
+            .select(())
+            .from(events)
+            .where_(eq(events.kind, "odd"))
+            .order(vec![desc(events.id)])
+            .limit_param(2);
+        let count = page.clone_for_count();
+
+        assert_eq!(
+            r#"select json_object('count', count(*)) from "events" where "events"."kind" = ?"#,
+            count.sql::<Total>()
+        );
+
+        let total: Total = count.first().await?;
+        let rows: Vec<Event> = page.all().await?;
+
+        assert_eq!(total.count, 3);
+        assert_eq!(rows.len(), 2);
+
+        let kinds = db
+            .select(())
+            .from(events)
+            .where_(gt(events.id, 1))
+            .distinct_on((events.kind,))
+            .limit_param(1);
+        let count = kinds.clone_for_count();
+
+        assert_eq!(
+            r#"select json_object('count', count(*)) from (select 1 from "events" where "events"."id" > ? group by "events"."kind")"#,
+            count.sql::<Total>()
+        );
+
+        let total: Total = count.first().await?;
+        assert_eq!(total.count, 2);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn insert_select_works() -> Result<(), ryzz::Error> {
+        use ryzz::*;
+
+        #[table("events")]
+        struct Event {
+            #[ryzz(pk)]
+            id: i64,
+            kind: String,
+        }
+
+        #[table("archive")]
+        struct Archive {
+            #[ryzz(pk)]
+            id: i64,
+            kind: String,
+        }
+
+        let db = Database::new(":memory:").await?;
+        let events = Event::table(&db).await?;
+        let archive = Archive::table(&db).await?;
+
+        for id in 1..=4 {
+            db.insert(events)
+                .values(Event {
+                    id,
+                    kind: if id % 2 == 0 { "even" } else { "odd" }.into(),
+                })?
+                .rows_affected()
+                .await?;
+        }
+
+        let query = db.insert_select(
+            archive,
+            (archive.id, archive.kind),
+            db.select((events.id, events.kind))
+                .from(events)
+                .where_(eq(events.kind, "even")),
+        );
+
+        assert_eq!(
+            r#"insert into "archive" ("id", "kind") select "events"."id", "events"."kind" from "events" where "events"."kind" = ?"#,
+            query.sql::<usize>()
+        );
+        assert_eq!(query.rows_affected().await?, 2);
+
+        let query = db.insert_select(
+            archive,
+            (),
+            db.select(()).from(events).where_(eq(events.id, 1)),
+        );
+        assert_eq!(query.rows_affected().await?, 1);
+
+        let rows: Vec<Archive> = db.select(()).from(archive).all().await?;
+        assert_eq!(
+            rows.iter().map(|row| row.id).collect::<Vec<_>>(),
+            vec![1, 2, 4]
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn truncate_works() -> Result<(), ryzz::Error> {
+        use ryzz::*;
+
+        #[table("events")]
+        struct Event {
+            #[ryzz(pk)]
+            id: Option<i64>,
+            name: String,
+        }
+
+        #[row]
+        struct Seq {
+            seq: i64,
+        }
+
+        let db = Database::new(":memory:").await?;
+        db.execute(
+            "create table events (id integer primary key autoincrement, name text not null)",
+        )
+        .await?;
+        let events = Event::table(&db).await?;
+
+        for name in ["a", "b", "c"] {
+            db.insert(events)
+                .values(Event {
+                    id: None,
+                    name: name.into(),
+                })?
+                .rows_affected()
+                .await?;
+        }
+
+        assert_eq!(db.truncate(events).await?, 3);
+
+        let rows: Vec<Event> = db.select(()).from(events).all().await?;
+        assert!(rows.is_empty());
+
+        let seq: Vec<Seq> = db
+            .query(Sql {
+                clause: "select json_object('seq', seq) from sqlite_sequence where name = 'events'"
+                    .into(),
+                params: vec![],
+            })
+            .await?;
+        assert!(seq.is_empty());
+
+        let event: Event = db
+            .insert(events)
+            .values(Event {
+                id: None,
+                name: "d".into(),
+            })?
+            .returning()
+            .await?;
+        assert_eq!(event.id, Some(1));
+
+        let tx = db.begin().await?;
+        assert_eq!(db.truncate(events).await?, 1);
+        tx.commit().await?;
+        let rows: Vec<Event> = db.select(()).from(events).all().await?;
+        assert!(rows.is_empty());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn where_with_works() -> Result<(), ryzz::Error> {
+        use ryzz::*;
+
+        #[table("accounts")]
+        struct Account {
+            #[ryzz(pk)]
+            id: i64,
+            name: String,
+            age: i64,
+        }
+
+        let db = Database::new(":memory:").await?;
+        let accounts = Account::table(&db).await?;
+
+        let name: Option<&str> = Some("a");
+        let min_age: Option<i64> = None;
+        let max_age: Option<i64> = Some(30);
+
+        let query = db.select(()).from(accounts).where_with(|w| {
+            if let Some(name) = name {
+                w.push(eq(accounts.name, name));
+            }
+            if let Some(age) = min_age {
+                w.push(gte(accounts.age, age));
+            }
+            if let Some(age) = max_age {
+                w.push(lt(accounts.age, age));
+            }
+        });
+
+        assert_eq!(
+            r#"select json_object('id', "accounts"."id",'name', "accounts"."name",'age', "accounts"."age") as "accounts" from "accounts" where ("accounts"."name" = ? and "accounts"."age" < ?)"#,
+            query.sql::<Account>()
+        );
+
+        let query = db.select(()).from(accounts).where_with(|_| {});
+        assert!(!query.sql::<Account>().contains("where"));
+
+        Ok(())
+    }
+
+    #[cfg(feature = "json")]
+    #[tokio::test]
+    async fn json_value_works() -> Result<(), ryzz::Error> {
+        use ryzz::*;
+
+        #[table("documents")]
+        struct Document {
+            #[ryzz(pk)]
+            id: i64,
+            #[serde(with = "ryzz::json")]
+            data: serde_json::Value,
+        }
+
+        let db = Database::new(":memory:").await?;
+        let documents = Document::table(&db).await?;
+
+        let data = serde_json::json!({ "tags": ["a", "b"], "nested": { "n": 1.5 } });
+        db.insert(documents)
+            .values(Document {
+                id: 1,
+                data: data.clone(),
+            })?
+            .rows_affected()
+            .await?;
+
+        let rows: Vec<Document> = db
+            .select(())
+            .from(documents)
+            .where_(eq(documents.data, data.clone()))
+            .all()
+            .await?;
+
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].data, data);
+
+        Ok(())
+    }
+
+    #[cfg(feature = "decimal")]
+    #[tokio::test]
+    async fn decimal_works() -> Result<(), ryzz::Error> {
+        use rust_decimal::Decimal;
+        use ryzz::*;
+        use std::str::FromStr;
+
+        #[table("payments")]
+        struct Payment {
+            #[ryzz(pk)]
+            id: i64,
+            #[serde(with = "ryzz::decimal")]
+            amount: rust_decimal::Decimal,
+        }
+
+        let db = Database::new(":memory:").await?;
+        let payments = Payment::table(&db).await?;
+
+        let amount = Decimal::from_str("1234567.8901234567890123").unwrap();
+        db.insert(payments)
+            .values(Payment { id: 1, amount })?
+            .rows_affected()
+            .await?;
+
+        let payment: Payment = db
+            .select(())
+            .from(payments)
+            .where_(eq(payments.amount, amount))
+            .first()
+            .await?;
+
+        assert_eq!(payment.amount, amount);
+        assert_eq!(payment.amount.to_string(), "1234567.8901234567890123");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn update_where_returning_works() -> Result<(), ryzz::Error> {
+        use ryzz::*;
+
+        #[table("accounts")]
+        struct Account {
+            #[ryzz(pk)]
+            id: i64,
+            name: String,
+        }
+
+        let db = Database::new(":memory:").await?;
+        let accounts = Account::table(&db).await?;
+
+        for (id, name) in [(1, "a"), (2, "b")] {
+            db.insert(accounts)
+                .values(Account {
+                    id,
+                    name: name.into(),
+                })?
+                .rows_affected()
+                .await?;
+        }
+
+        let account: Account = db
+            .update(accounts)
+            .where_(and(eq(accounts.id, 2), eq(accounts.name, "b")))
+            .set(Account {
+                id: 2,
+                name: "c".into(),
+            })?
+            .returning()
+            .await?;
+
+        assert_eq!(account.id, 2);
+        assert_eq!(account.name, "c");
+
+        let query = db
+            .update(accounts)
+            .set(Account {
+                id: 1,
+                name: "d".into(),
+            })?
+            .where_(eq(accounts.id, 1));
+
+        assert_eq!(
+            vec![
+                Value::Integer(1),
+                Value::Text("d".into()),
+                Value::Integer(1)
+            ],
+            query.sql_statement::<usize>().params
+        );
+
+        let rows: Vec<Account> = db.select(()).from(accounts).all().await?;
+        assert_eq!(rows[0].name, "a");
+        assert_eq!(rows[1].name, "c");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn not_in_works() -> Result<(), ryzz::Error> {
+        use ryzz::*;
+
+        #[table("accounts")]
+        struct Account {
+            #[ryzz(pk)]
+            id: i64,
+            name: Option<String>,
+        }
+
+        let db = Database::new(":memory:").await?;
+        let accounts = Account::table(&db).await?;
+
+        for (id, name) in [(1, Some("a")), (2, Some("b")), (3, None)] {
+            db.insert(accounts)
+                .values(Account {
+                    id,
+                    name: name.map(|name| name.into()),
+                })?
+                .rows_affected()
+                .await?;
+        }
+
+        let query = db
+            .select(())
+            .from(accounts)
+            .where_(not_in(accounts.id, vec![1, 3]));
+        assert!(query
+            .sql::<Account>()
+            .ends_with(r#"where "accounts"."id" not in (?,?)"#));
+        let rows: Vec<Account> = query.all().await?;
+        assert_eq!(rows.iter().map(|row| row.id).collect::<Vec<_>>(), vec![2]);
+
+        let query = db
+            .select(())
+            .from(accounts)
+            .where_(not_in(accounts.name, Vec::<String>::new()));
+        assert!(query
+            .sql::<Account>()
+            .ends_with(r#"where "accounts"."name" not in ()"#));
+        let rows: Vec<Account> = query.all().await?;
+        assert_eq!(
+            rows.iter().map(|row| row.id).collect::<Vec<_>>(),
+            vec![1, 2, 3]
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn like_escape_works() -> Result<(), ryzz::Error> {
+        use ryzz::*;
+
+        #[table("discounts")]
+        struct Discount {
+            #[ryzz(pk)]
+            id: i64,
+            label: String,
+        }
+
+        let db = Database::new(":memory:").await?;
+        let discounts = Discount::table(&db).await?;
+
+        for (id, label) in [(1, "50% off"), (2, "500 off"), (3, "a_b\\c")] {
+            db.insert(discounts)
+                .values(Discount {
+                    id,
+                    label: label.into(),
+                })?
+                .rows_affected()
+                .await?;
+        }
+
+        assert_eq!(escape_like("50%", '\\'), "50\\%");
+
+        let pattern = format!("%{}%", escape_like("50%", '\\'));
+        let query =
+            db.select(())
+                .from(discounts)
+                .where_(like_escape(discounts.label, pattern, '\\'));
+        assert!(query
+            .sql::<Discount>()
+            .ends_with(r#"where "discounts"."label" like ? escape '\'"#));
+        let rows: Vec<Discount> = query.all().await?;
+        assert_eq!(rows.iter().map(|row| row.id).collect::<Vec<_>>(), vec![1]);
+
+        let pattern = escape_like("a_b\\c", '\\');
+        let rows: Vec<Discount> = db
+            .select(())
+            .from(discounts)
+            .where_(like_escape(discounts.label, pattern, '\\'))
+            .all()
+            .await?;
+        assert_eq!(rows.iter().map(|row| row.id).collect::<Vec<_>>(), vec![3]);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn order_by_col_works() -> Result<(), ryzz::Error> {
+        use ryzz::*;
+
+        #[table("accounts")]
+        struct Account {
+            #[ryzz(pk)]
+            id: i64,
+            name: String,
+        }
+
+        let db = Database::new(":memory:").await?;
+        let accounts = Account::table(&db).await?;
+
+        for (id, name) in [(1, "b"), (2, "a"), (3, "a")] {
+            db.insert(accounts)
+                .values(Account {
+                    id,
+                    name: name.into(),
+                })?
+                .rows_affected()
+                .await?;
+        }
+
+        let query = db
+            .select(())
+            .from(accounts)
+            .order(vec![order_by_col(accounts.id, Order::Asc)]);
+        assert!(query
+            .sql::<Account>()
+            .ends_with(r#"order by "accounts"."id" asc"#));
+        let rows: Vec<Account> = query.all().await?;
+        assert_eq!(
+            rows.iter().map(|row| row.id).collect::<Vec<_>>(),
+            vec![1, 2, 3]
+        );
+
+        let query = db
+            .select(())
+            .from(accounts)
+            .order(vec![order_by_col(accounts.name, Order::Asc)])
+            .order(vec![order_by_col(accounts.id, Order::Desc)]);
+        assert!(query
+            .sql::<Account>()
+            .ends_with(r#"order by "accounts"."name" asc,"accounts"."id" desc"#));
+        let rows: Vec<Account> = query.all().await?;
+        assert_eq!(
+            rows.iter().map(|row| row.id).collect::<Vec<_>>(),
+            vec![3, 2, 1]
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn table_and_column_exists_work() -> Result<(), ryzz::Error> {
+        use ryzz::*;
+
+        #[table("accounts")]
+        struct Account {
+            #[ryzz(pk)]
+            id: i64,
+            name: String,
+        }
+
+        let db = Database::new(":memory:").await?;
+        assert!(!db.table_exists("accounts").await?);
+
+        Account::table(&db).await?;
+
+        assert!(db.table_exists("accounts").await?);
+        assert!(!db.table_exists("posts").await?);
+        assert!(db.column_exists("accounts", "name").await?);
+        assert!(!db.column_exists("accounts", "email").await?);
+        assert!(!db.column_exists("posts", "name").await?);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn list_tables_and_columns_work() -> Result<(), ryzz::Error> {
+        use ryzz::*;
+
+        #[table("accounts")]
+        struct Account {
+            #[ryzz(pk)]
+            id: i64,
+            name: String,
+            email: Option<String>,
+        }
+
+        let db = Database::new(":memory:").await?;
+        db.execute("create table events (id integer primary key autoincrement)")
+            .await?;
+        Account::table(&db).await?;
+
+        assert_eq!(db.list_tables().await?, vec!["accounts", "events"]);
+        assert_eq!(
+            db.list_columns("accounts").await?,
+            vec![
+                ColumnInfo {
+                    name: "id".into(),
+                    sql_type: "INTEGER".into(),
+                    not_null: true,
+                    primary_key: true,
+                },
+                ColumnInfo {
+                    name: "name".into(),
+                    sql_type: "TEXT".into(),
+                    not_null: true,
+                    primary_key: false,
+                },
+                ColumnInfo {
+                    name: "email".into(),
+                    sql_type: "TEXT".into(),
+                    not_null: false,
+                    primary_key: false,
+                },
+            ]
+        );
+        assert!(db.list_columns("posts").await?.is_empty());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn table_columns_works() -> Result<(), ryzz::Error> {
+        use ryzz::*;
+
+        #[table("accounts")]
+        struct Accounts {
+            #[ryzz(pk)]
+            id: i64,
+            #[ryzz(unique)]
+            email: String,
+            #[ryzz(name = "display_name")]
+            name: Option<String>,
+            score: f64,
+        }
+
+        let db = Database::new(":memory:").await?;
+        let accounts = Accounts::table(&db).await?;
+
+        assert_eq!(
+            accounts.columns(),
+            vec![
+                ColumnDef {
+                    name: "id",
+                    sql_type: "Integer",
+                    primary_key: true,
+                    not_null: true,
+                    unique: false,
+                },
+                ColumnDef {
+                    name: "email",
+                    sql_type: "Text",
+                    primary_key: false,
+                    not_null: true,
+                    unique: true,
+                },
+                ColumnDef {
+                    name: "display_name",
+                    sql_type: "Text",
+                    primary_key: false,
+                    not_null: false,
+                    unique: false,
+                },
+                ColumnDef {
+                    name: "score",
+                    sql_type: "Real",
+                    primary_key: false,
+                    not_null: true,
+                    unique: false,
+                },
+            ]
+        );
+
+        let info = db.list_columns("accounts").await?;
+        for (def, info) in accounts.columns().iter().zip(&info) {
+            assert_eq!(def.name, info.name);
+            assert!(def.sql_type.eq_ignore_ascii_case(&info.sql_type));
+            assert_eq!(def.not_null, info.not_null);
+            assert_eq!(def.primary_key, info.primary_key);
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn i128_and_u128_work() -> Result<(), ryzz::Error> {
+        use ryzz::*;
+
+        #[table("ids")]
+        struct Id {
+            #[ryzz(pk)]
+            id: i64,
+            #[serde(with = "ryzz::as_text")]
+            big: i128,
+            #[serde(with = "ryzz::as_text")]
+            unsigned: u128,
+        }
+
+        let db = Database::new(":memory:").await?;
+        let ids = Id::table(&db).await?;
+
+        let big = i64::MAX as i128 * 1000 + 7;
+        let unsigned = u128::MAX;
+        db.insert(ids)
+            .values(Id {
+                id: 1,
+                big,
+                unsigned,
+            })?
+            .rows_affected()
+            .await?;
+
+        let row: Id = db
+            .select(())
+            .from(ids)
+            .where_(eq(ids.big, as_text::value(big)))
+            .first()
+            .await?;
+
+        assert_eq!(row.big, big);
+        assert_eq!(row.unsigned, unsigned);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn from_as_works() -> Result<(), ryzz::Error> {
+        use ryzz::*;
+
+        #[table("employees")]
+        struct Employee {
+            #[ryzz(pk)]
+            id: i64,
+            name: String,
+            manager_id: Option<i64>,
+        }
+
+        #[row]
+        struct EmployeeManager {
+            employee: Employee,
+            manager: Employee,
+        }
+
+        let db = Database::new(":memory:").await?;
+        let employees = Employee::table(&db).await?;
+
+        for (id, name, manager_id) in [(1, "boss", None), (2, "worker", Some(1))] {
+            db.insert(employees)
+                .values(Employee {
+                    id,
+                    name: name.into(),
+                    manager_id,
+                })?
+                .rows_affected()
+                .await?;
+        }
+
+        let query = db
+            .select(())
+            .from_as(employees, "e")
+            .inner_join_as(
+                employees,
+                "m",
+                eq(
+                    aliased("m", employees.id),
+                    aliased("e", employees.manager_id),
+                ),
+            )
+            .where_(eq(aliased("e", employees.name), "worker"));
+
+        assert_eq!(
+            r#"select json_object('employee', json_object('id', "e"."id",'name', "e"."name",'manager_id', "e"."manager_id") ,'manager', json_object('id', "m"."id",'name', "m"."name",'manager_id', "m"."manager_id") ) from "employees" as "e" inner  join "employees" as "m" on "m"."id" = "e"."manager_id" where "e"."name" = ?"#,
+            query.sql::<EmployeeManager>()
+        );
+
+        let rows: Vec<EmployeeManager> = query.all().await?;
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].employee.name, "worker");
+        assert_eq!(rows[0].manager.name, "boss");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn delete_where_in_works() -> Result<(), ryzz::Error> {
+        use ryzz::*;
+
+        #[table("events")]
+        struct Event {
+            #[ryzz(pk)]
+            id: i64,
+        }
+
+        let db = Database::new(":memory:").await?;
+        let events = Event::table(&db).await?;
+
+        for id in 1..=5 {
+            db.insert(events)
+                .values(Event { id })?
+                .rows_affected()
+                .await?;
+        }
+
+        let deleted = db
+            .delete_where_in(events, events.id, vec![1, 3, 5])
+            .rows_affected()
+            .await?;
+        assert_eq!(deleted, 3);
+
+        let deleted = db
+            .delete_where_in(events, events.id, Vec::<i64>::new())
+            .rows_affected()
+            .await?;
+        assert_eq!(deleted, 0);
+
+        let rows: Vec<Event> = db.select(()).from(events).all().await?;
+        assert_eq!(
+            rows.iter().map(|row| row.id).collect::<Vec<_>>(),
+            vec![2, 4]
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn pragma_query_works() -> Result<(), ryzz::Error> {
+        use ryzz::*;
+
+        let db = Database::with(Connection::default(":memory:").journal_mode(JournalMode::Memory))
+            .await?;
+
+        let journal_mode: String = db.pragma_query("journal_mode").await?;
+        let page_size: i64 = db.pragma_query("page_size").await?;
+
+        assert_eq!(journal_mode, "memory");
+        assert!(page_size > 0);
+        assert!(db.pragma_query::<i64>("not a pragma").await.is_err());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn size_on_disk_works() -> Result<(), ryzz::Error> {
+        use ryzz::*;
+
+        #[table("events")]
+        struct Event {
+            #[ryzz(pk)]
+            id: i64,
+        }
+
+        let db = Database::new(":memory:").await?;
+        let events = Event::table(&db).await?;
+        db.insert(events)
+            .values(Event { id: 1 })?
+            .rows_affected()
+            .await?;
+
+        let size = db.size_on_disk().await?;
+        let page_size: u64 = db.pragma_query("page_size").await?;
+
+        assert!(size > 0);
+        assert_eq!(size % page_size, 0);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn sql_macro_works() -> Result<(), ryzz::Error> {
+        use ryzz::*;
+
+        #[row]
+        struct Event {
+            id: i64,
+            name: Option<String>,
+        }
+
+        let db = Database::new(":memory:").await?;
+        db.execute("create table events (id integer primary key, name text)")
+            .await?;
+
+        let insert = sql!(
+            "insert into events (id, name) values (?, ?), (?, ?)",
+            1,
+            "a",
+            2,
+            Value::Null
+        );
+        assert_eq!(
+            insert.params,
+            vec![
+                Value::Integer(1),
+                Value::Text("a".into()),
+                Value::Integer(2),
+                Value::Null
+            ]
+        );
+        assert_eq!(db.execute_sql(insert).await?, 2);
+
+        let rows: Vec<Event> = db
+            .query(sql!(
+                "select json_object('id', id, 'name', name) from events where id > ? order by id",
+                0
+            ))
+            .await?;
+
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0].name.as_deref(), Some("a"));
+        assert_eq!(rows[1].name, None);
+
+        let count = sql!("select count(*) from events");
+        assert!(count.params.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    #[allow(unused)]
+    fn sql_parts_works() {
+        use ryzz::*;
+
+        #[table("accounts")]
+        struct Account {
+            #[ryzz(pk)]
+            id: i64,
+            name: String,
+        }
+
+        let accounts = AccountTable::new();
+        let sql = and(
+            eq(accounts.id, 1),
+            or(eq(accounts.name, "a"), like(accounts.name, "b%")),
+        );
+
+        let (clause, params) = sql.parts();
+        assert_eq!(
+            clause,
+            r#"("accounts"."id" = ? and ("accounts"."name" = ? or "accounts"."name" like ?))"#
+        );
+        assert_eq!(
+            params,
+            &[
+                Value::Integer(1),
+                Value::Text("a".into()),
+                Value::Text("b%".into())
+            ]
+        );
+        assert!(format!("{:?}", sql).contains(r#"\"accounts\".\"id\" = ?"#));
+    }
+
+    #[tokio::test]
+    async fn transaction_immediate_works() -> Result<(), ryzz::Error> {
+        use ryzz::*;
+        use std::time::Duration;
+
+        #[row]
+        struct Log {
+            entry: String,
+        }
+
+        let path =
+            std::env::temp_dir().join(format!("ryzz-immediate-{}.sqlite3", std::process::id()));
+        let path = path.to_str().unwrap();
+        let _ = std::fs::remove_file(path);
+
+        let db = Database::new(path).await?;
+        db.execute("create table log (id integer primary key, entry text not null)")
+            .await?;
+        let other = Database::new(path).await?;
+
+        let write = |db: Database, name: &'static str| async move {
+            db.transaction_immediate(|tx| async move {
+                tx.execute(&format!("insert into log (entry) values ('{name} start')"))
+                    .await?;
+                tokio::time::sleep(Duration::from_millis(50)).await;
+                tx.execute(&format!("insert into log (entry) values ('{name} end')"))
+                    .await?;
+                Ok(())
+            })
+            .await
+        };
+
+        let (a, b) = tokio::join!(
+            tokio::spawn(write(db.clone(), "a")),
+            tokio::spawn(write(other, "b"))
+        );
+        a.unwrap()?;
+        b.unwrap()?;
+
+        let log: Vec<Log> = db
+            .query(sql!(
+                "select json_object('entry', entry) from log order by id"
+            ))
+            .await?;
+        let log = log.into_iter().map(|row| row.entry).collect::<Vec<_>>();
+
+        assert!(
+            log == ["a start", "a end", "b start", "b end"]
+                || log == ["b start", "b end", "a start", "a end"],
+            "{log:?}"
+        );
+
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = std::fs::remove_file(format!("{path}{suffix}"));
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn returning_cols_works() -> Result<(), ryzz::Error> {
+        use ryzz::*;
+
+        #[table("accounts")]
+        struct Account {
+            #[ryzz(pk)]
+            id: Option<i64>,
+            name: String,
+            bio: String,
+        }
+
+        #[row]
+        struct AccountId {
+            id: i64,
+        }
+
+        let db = Database::new(":memory:").await?;
+        let accounts = Account::table(&db).await?;
+
+        let inserted: AccountId = db
+            .insert(accounts)
+            .values(Account {
+                id: None,
+                name: "a".into(),
+                bio: "long".into(),
+            })?
+            .returning_cols(accounts.id)
+            .await?;
+        assert_eq!(inserted.id, 1);
+
+        let query = db.insert(accounts).values(Account {
+            id: None,
+            name: "b".into(),
+            bio: "long".into(),
+        })?;
+        let inserted: AccountId = query.returning_cols((accounts.id,)).await?;
+        assert_eq!(inserted.id, 2);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn upsert_works() -> Result<(), ryzz::Error> {
+        use ryzz::*;
+
+        #[table("accounts")]
+        struct Account {
+            #[ryzz(pk)]
+            id: i64,
+            #[ryzz(unique)]
+            email: String,
+            name: String,
+        }
+
+        let db = Database::new(":memory:").await?;
+        let accounts = Account::table(&db).await?;
+
+        let account = Account {
+            id: 1,
+            email: "a@example.com".into(),
+            name: "a".into(),
+        };
+        let query = db.upsert(accounts, account.clone(), accounts.email)?;
+        assert_eq!(
+            r#"insert into "accounts" ("id","email","name") values (?,?,?) on conflict ("email") do update set "id" = excluded."id","name" = excluded."name""#,
+            query.sql::<usize>()
+        );
+        assert_eq!(query.rows_affected().await?, 1);
+
+        let account = Account {
+            name: "b".into(),
+            ..account
+        };
+        let upserted: Account = db
+            .upsert(accounts, account, accounts.email)?
+            .returning()
+            .await?;
+        assert_eq!(upserted.name, "b");
+
+        let rows: Vec<Account> = db.select(()).from(accounts).all().await?;
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].name, "b");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn insert_or_works() -> Result<(), ryzz::Error> {
+        use ryzz::*;
+
+        #[table("accounts")]
+        struct Account {
+            #[ryzz(pk)]
+            id: i64,
+            name: String,
+        }
+
+        let db = Database::new(":memory:").await?;
+        let accounts = Account::table(&db).await?;
+
+        db.insert(accounts)
+            .values(Account {
+                id: 1,
+                name: "a".into(),
+            })?
+            .rows_affected()
+            .await?;
+
+        let query = db.insert_or(accounts, OnConflict::Ignore).values(Account {
+            id: 1,
+            name: "b".into(),
+        })?;
+        assert_eq!(
+            r#"insert or ignore into "accounts" ("id","name") values (?,?)"#,
+            query.sql::<usize>()
+        );
+        assert_eq!(query.rows_affected().await?, 0);
+
+        let account: Account = db.select(()).from(accounts).first().await?;
+        assert_eq!(account.name, "a");
+
+        let affected = db
+            .insert_or(accounts, OnConflict::Replace)
+            .values(Account {
+                id: 1,
+                name: "c".into(),
+            })?
+            .rows_affected()
+            .await?;
+        assert_eq!(affected, 1);
+
+        let rows: Vec<Account> = db.select(()).from(accounts).all().await?;
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].name, "c");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn params_works() -> Result<(), ryzz::Error> {
+        use ryzz::*;
+
+        #[table("accounts")]
+        struct Account {
+            #[ryzz(pk)]
+            id: i64,
+            name: String,
+        }
+
+        let db = Database::new(":memory:").await?;
+        let accounts = Account::table(&db).await?;
+
+        let query = db
+            .select(())
+            .from(accounts)
+            .where_(and(eq(accounts.name, "a"), gt(accounts.id, 10)))
+            .limit_param(5);
+
+        assert_eq!(
+            query.params(),
+            vec![
+                Value::Text("a".into()),
+                Value::Integer(10),
+                Value::Integer(5)
+            ]
+        );
+        assert!(db.select(()).from(accounts).params().is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    #[allow(unused)]
+    fn table_column_works() {
+        use ryzz::*;
+
+        #[table("accounts")]
+        struct Account {
+            #[ryzz(pk)]
+            id: i64,
+            #[ryzz(name = "display_name")]
+            name: String,
+        }
+
+        let accounts = AccountTable::new();
+
+        assert_eq!(accounts.column("id"), Some(r#""accounts"."id""#));
+        assert_eq!(
+            accounts.column("display_name"),
+            Some(r#""accounts"."display_name""#)
+        );
+        assert_eq!(
+            accounts.column("name"),
+            Some(r#""accounts"."display_name""#)
+        );
+        assert_eq!(accounts.column("email"), None);
+    }
+
+    #[tokio::test]
+    async fn values_order_matches_columns() -> Result<(), ryzz::Error> {
+        use ryzz::*;
+
+        #[table("accounts")]
+        struct Account {
+            #[ryzz(pk)]
+            id: i64,
+            name: String,
+            email: String,
+        }
+
+        #[row]
+        struct NewAccount {
+            email: String,
+            id: i64,
+            name: String,
+        }
+
+        let db = Database::new(":memory:").await?;
+        let accounts = Account::table(&db).await?;
+
+        let query = db.insert(accounts).values(NewAccount {
+            email: "a@example.com".into(),
+            id: 1,
+            name: "a".into(),
+        })?;
+
+        assert_eq!(
+            r#"insert into "accounts" ("email","id","name") values (?,?,?)"#,
+            query.sql::<usize>()
+        );
+        assert_eq!(
+            query.params(),
+            vec![
+                Value::Text("a@example.com".into()),
+                Value::Integer(1),
+                Value::Text("a".into())
+            ]
+        );
+        query.rows_affected().await?;
+
+        let account: Account = db.select(()).from(accounts).first().await?;
+        assert_eq!(account.id, 1);
+        assert_eq!(account.name, "a");
+        assert_eq!(account.email, "a@example.com");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn without_rowid_works() -> Result<(), ryzz::Error> {
+        use ryzz::*;
+
+        #[table("settings")]
+        #[ryzz(without_rowid)]
+        struct Setting {
+            #[ryzz(pk)]
+            key: String,
+            value: String,
+        }
+
+        let db = Database::new(":memory:").await?;
+        let settings = Setting::table(&db).await?;
+
+        assert_eq!(
+            r#"create table if not exists "settings" ("key" Text not null primary key,"value" Text not null) without rowid;"#,
+            settings.create_table_sql()
+        );
+
+        db.insert(settings)
+            .values(Setting {
+                key: "theme".into(),
+                value: "dark".into(),
+            })?
+            .rows_affected()
+            .await?;
+
+        let setting: Setting = db
+            .select(())
+            .from(settings)
+            .where_(eq(settings.key, "theme"))
+            .first()
+            .await?;
+        assert_eq!(setting.value, "dark");
+
+        let rowid: Result<Vec<Setting>, Error> = db
+            .query(sql!("select json_object('key', rowid) from settings"))
+            .await;
+        assert!(rowid.is_err());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn strict_works() -> Result<(), ryzz::Error> {
+        use ryzz::*;
+
+        #[table("scores")]
+        #[ryzz(strict)]
+        struct Score {
+            #[ryzz(pk)]
+            id: i64,
+            points: i64,
+        }
+
+        let db = Database::new(":memory:").await?;
+        let scores = Score::table(&db).await?;
+
+        assert_eq!(
+            r#"create table if not exists "scores" ("id" Integer not null primary key,"points" Integer not null) strict;"#,
+            scores.create_table_sql()
+        );
+
+        db.insert(scores)
+            .values(Score { id: 1, points: 10 })?
+            .rows_affected()
+            .await?;
+
+        let err = db
+            .execute_sql(sql!(
+                "insert into scores (id, points) values (?, ?)",
+                2,
+                "lots"
+            ))
+            .await
+            .unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("cannot store TEXT value in INTEGER column"));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn distinct_on_works() -> Result<(), ryzz::Error> {
+        use ryzz::*;
+
+        #[table("events")]
+        struct Event {
+            #[ryzz(pk)]
+            id: i64,
+            account_id: i64,
+            kind: String,
+        }
+
+        let db = Database::new(":memory:").await?;
+        let events = Event::table(&db).await?;
+
+        for (id, account_id, kind) in [(1, 1, "a"), (2, 1, "a"), (3, 1, "b"), (4, 2, "a")] {
+            db.insert(events)
+                .values(Event {
+                    id,
+                    account_id,
+                    kind: kind.into(),
+                })?
+                .rows_affected()
+                .await?;
+        }
+
+        let query = db
+            .select(())
+            .from(events)
+            .distinct_on((events.account_id, events.kind))
+            .order(vec![asc(events.account_id), asc(events.kind)]);
+        assert!(query.sql::<Event>().ends_with(
+            r#"group by "events"."account_id","events"."kind" order by "events"."account_id" asc,"events"."kind" asc"#
+        ));
+
+        let rows: Vec<Event> = query.all().await?;
+        assert_eq!(
+            rows.iter()
+                .map(|row| (row.account_id, row.kind.as_str()))
+                .collect::<Vec<_>>(),
+            vec![(1, "a"), (1, "b"), (2, "a")]
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn with_replica_works() -> Result<(), ryzz::Error> {
+        use ryzz::*;
+
+        #[table("accounts")]
+        struct Account {
+            #[ryzz(pk)]
+            id: i64,
+            name: String,
+        }
+
+        let replica = Database::new(":memory:").await?;
+        let accounts = Account::table(&replica).await?;
+        replica
+            .insert(accounts)
+            .values(Account {
+                id: 1,
+                name: "replica".into(),
+            })?
+            .rows_affected()
+            .await?;
+
+        let events_seen = Arc::new(std::sync::Mutex::new(vec![]));
+        let seen = events_seen.clone();
+        let db = Database::new(":memory:")
+            .await?
+            .with_replica(replica)
+            .on_query(move |event| seen.lock().unwrap().push(event.sql));
+        Account::table(&db).await?;
+        db.insert(accounts)
+            .values(Account {
+                id: 2,
+                name: "primary".into(),
+            })?
+            .rows_affected()
+            .await?;
+
+        let rows: Vec<Account> = db.select(()).from(accounts).all().await?;
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].name, "replica");
+
+        let rows: Vec<Account> = db.select_primary(()).from(accounts).all().await?;
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].name, "primary");
+
+        // table() reads the schema off the primary and adds columns with the database's options
+        #[table("accounts")]
+        struct NamedAccount {
+            #[ryzz(pk)]
+            id: i64,
+            name: String,
+            nickname: Option<String>,
+        }
+        events_seen.lock().unwrap().clear();
+        NamedAccount::table(&db).await?;
+        let seen = events_seen.lock().unwrap();
+        assert!(seen
+            .iter()
+            .any(|sql| sql.contains(r#"from "sqlite_schema""#)));
+        assert!(seen
+            .iter()
+            .any(|sql| sql.starts_with(r#"alter table "accounts" add column "nickname""#)));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn prepared_query_works() -> Result<(), ryzz::Error> {
+        use ryzz::*;
+
+        #[table("accounts")]
+        struct Account {
+            #[ryzz(pk)]
+            id: i64,
+            name: String,
+        }
+
+        let db = Database::new(":memory:").await?;
+        let accounts = Account::table(&db).await?;
+
+        for id in 0..100 {
+            db.insert(accounts)
+                .values(Account {
+                    id,
+                    name: format!("account {id}"),
+                })?
+                .rows_affected()
+                .await?;
+        }
+
+        let by_id = db
+            .select(())
+            .from(accounts)
+            .where_(eq(accounts.id, Value::Integer(0)))
+            .prepare::<Account>();
+
+        for id in 0..100 {
+            let account = by_id.first(vec![Value::Integer(id)]).await?;
+            assert_eq!(account.name, format!("account {id}"));
+        }
+
+        assert!(by_id.all(vec![]).await.is_err());
+
+        let rename = db
+            .update(accounts)
+            .set(Account {
+                id: 0,
+                name: "".into(),
+            })?
+            .where_(eq(accounts.id, 0))
+            .prepare::<usize>();
+        let affected = rename
+            .execute(vec![
+                Value::Integer(5),
+                Value::Text("renamed".into()),
+                Value::Integer(5),
+            ])
+            .await?;
+        assert_eq!(affected, 1);
+        assert_eq!(by_id.first(vec![Value::Integer(5)]).await?.name, "renamed");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn sql_output_is_unchanged() -> Result<(), ryzz::Error> {
+        use ryzz::*;
+
+        #[table("accounts")]
+        struct Account {
+            #[ryzz(pk)]
+            id: i64,
+            name: String,
+        }
+
+        #[table("posts")]
+        struct Post {
+            #[ryzz(pk)]
+            id: i64,
+            account_id: i64,
+        }
+
+        #[row]
+        struct AccountPost {
+            account: Account,
+            post: Post,
+        }
+
+        let db = Database::new(":memory:").await?;
+        let accounts = Account::table(&db).await?;
+        let posts = Post::table(&db).await?;
+
+        let query = db
+            .select(())
+            .from(accounts)
+            .inner_join(posts, eq(posts.account_id, accounts.id))
+            .where_(eq(accounts.name, "a"))
+            .group_by(vec![accounts.id])
+            .order(vec![desc(accounts.id)])
+            .limit(10);
+        assert_eq!(
+            r#"select json_object('account', json_object('id', "accounts"."id",'name', "accounts"."name") ,'post', json_object('id', "posts"."id",'account_id', "posts"."account_id") ) from "accounts" inner  join "posts"  on "posts"."account_id" = "accounts"."id" where "accounts"."name" = ? group by "accounts"."id" order by "accounts"."id" desc limit 10"#,
+            query.sql::<AccountPost>()
+        );
+
+        let query = db
+            .update(accounts)
+            .set(Account {
+                id: 1,
+                name: "b".into(),
+            })?
+            .where_(eq(accounts.id, 1));
+        assert_eq!(
+            r#"update "accounts" set "id" = ?,"name" = ? where "accounts"."id" = ?"#,
+            query.sql::<usize>()
+        );
+
+        let query = db.delete_from(accounts).where_(eq(accounts.id, 1));
+        assert_eq!(
+            r#"delete from "accounts" where "accounts"."id" = ?"#,
+            query.sql::<usize>()
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn fragments_are_shared() -> Result<(), ryzz::Error> {
+        use ryzz::*;
+
+        #[table("accounts")]
+        struct Account {
+            #[ryzz(pk)]
+            id: i64,
+        }
+
+        let db = Database::new(":memory:").await?;
+        let accounts = Account::table(&db).await?;
+
+        let a = db.delete_from(accounts).where_(eq(accounts.id, 1));
+        let b = db.delete_from(accounts).where_(eq(accounts.id, 2));
+        assert!(std::sync::Arc::ptr_eq(
+            a.delete.as_ref().unwrap(),
+            b.delete.as_ref().unwrap()
+        ));
+
+        let a = db.insert(accounts);
+        let b = db.insert(accounts);
+        assert!(std::sync::Arc::ptr_eq(
+            a.insert_into.as_ref().unwrap(),
+            b.insert_into.as_ref().unwrap()
+        ));
+
+        let a = db.select(()).from(accounts);
+        let b = db.select(()).from(accounts);
+        assert!(std::sync::Arc::ptr_eq(
+            a.from_sql.as_ref().unwrap(),
+            b.from_sql.as_ref().unwrap()
+        ));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn execute_many_works() -> Result<(), ryzz::Error> {
+        use ryzz::*;
+
+        #[table("events")]
+        struct Event {
+            #[ryzz(pk)]
+            id: i64,
+            name: String,
+        }
+
+        let db = Database::new(":memory:").await?;
+        let events = Event::table(&db).await?;
+
+        let param_sets = (0..500)
+            .map(|i| vec![Value::Integer(i), Value::Text(format!("event {i}"))])
+            .collect::<Vec<_>>();
+        let affected = db
+            .execute_many("insert into events (id, name) values (?, ?)", param_sets)
+            .await?;
+        assert_eq!(affected, 500);
+
+        let rows: Vec<Event> = db.select(()).from(events).all().await?;
+        assert_eq!(rows.len(), 500);
+        assert_eq!(rows[499].name, "event 499");
+
+        let duplicate = vec![
+            vec![Value::Integer(500), Value::Text("new".into())],
+            vec![Value::Integer(0), Value::Text("duplicate".into())],
+        ];
+        assert!(db
+            .execute_many("insert into events (id, name) values (?, ?)", duplicate)
+            .await
+            .is_err());
+        let rows: Vec<Event> = db.select(()).from(events).all().await?;
+        assert_eq!(rows.len(), 500);
+
+        let tx = db.begin().await?;
+        let more = vec![vec![Value::Integer(500), Value::Text("event 500".into())]];
+        db.execute_many("insert into events (id, name) values (?, ?)", more)
+            .await?;
+        tx.commit().await?;
+        let rows: Vec<Event> = db.select(()).from(events).all().await?;
+        assert_eq!(rows.len(), 501);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn read_uncommitted_works() -> Result<(), ryzz::Error> {
+        use ryzz::*;
+
+        let conn = Connection::new(":memory:").read_uncommitted(true);
+        assert_eq!(
+            conn.pragma.as_deref(),
+            Some("PRAGMA read_uncommitted = ON;")
+        );
+        let db = Database::with(conn).await?;
+        assert_eq!(db.pragma_query::<i64>("read_uncommitted").await?, 1);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn not_like_and_glob_work() -> Result<(), ryzz::Error> {
+        use ryzz::*;
+
+        #[table("files")]
+        struct File {
+            #[ryzz(pk)]
+            id: i64,
+            path: String,
+        }
+
+        let db = Database::new(":memory:").await?;
+        let files = File::table(&db).await?;
+
+        for (id, path) in [(1, "src/lib.rs"), (2, "README.md"), (3, "src/Main.rs")] {
+            db.insert(files)
+                .values(File {
+                    id,
+                    path: path.into(),
+                })?
+                .rows_affected()
+                .await?;
+        }
+
+        let ids = |rows: Vec<File>| rows.iter().map(|row| row.id).collect::<Vec<_>>();
+
+        let query = db
+            .select(())
+            .from(files)
+            .where_(not_like(files.path, "src/%"));
+        assert!(query
+            .sql::<File>()
+            .ends_with(r#"where "files"."path" not like ?"#));
+        assert_eq!(ids(query.all().await?), vec![2]);
+
+        let query = db
+            .select(())
+            .from(files)
+            .where_(glob(files.path, "src/[a-z]*"));
+        assert!(query
+            .sql::<File>()
+            .ends_with(r#"where "files"."path" glob ?"#));
+        assert_eq!(ids(query.all().await?), vec![1]);
+
+        let query = db
+            .select(())
+            .from(files)
+            .where_(not_glob(files.path, "*.rs"));
+        assert!(query
+            .sql::<File>()
+            .ends_with(r#"where "files"."path" not glob ?"#));
+        assert_eq!(ids(query.all().await?), vec![2]);
+
+        let rows: Vec<File> = db
+            .select(())
+            .from(files)
+            .where_(and(
+                not_like(files.path, "%.md"),
+                or(not_glob(files.path, "*lib*"), eq(files.id, 1)),
+            ))
+            .order(vec![asc(files.id)])
+            .all()
+            .await?;
+        assert_eq!(ids(rows), vec![1, 3]);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn is_and_is_not_work() -> Result<(), ryzz::Error> {
+        use ryzz::*;
+
+        #[table("contacts")]
+        struct Contact {
+            #[ryzz(pk)]
+            id: i64,
+            email: Option<String>,
+        }
+
+        let db = Database::new(":memory:").await?;
+        let contacts = Contact::table(&db).await?;
+
+        for (id, email) in [(1, Some("a@b.c")), (2, None), (3, Some("d@e.f"))] {
+            db.insert(contacts)
+                .values(Contact {
+                    id,
+                    email: email.map(String::from),
+                })?
+                .rows_affected()
+                .await?;
+        }
+
+        let ids = |rows: Vec<Contact>| rows.iter().map(|row| row.id).collect::<Vec<_>>();
+
+        let query = db
+            .select(())
+            .from(contacts)
+            .where_(is(contacts.email, Value::Null));
+        assert!(query
+            .sql::<Contact>()
+            .ends_with(r#"where "contacts"."email" is null"#));
+        assert_eq!(ids(query.all().await?), vec![2]);
+
+        let query = db
+            .select(())
+            .from(contacts)
+            .where_(is(contacts.email, "a@b.c"));
+        assert!(query
+            .sql::<Contact>()
+            .ends_with(r#"where "contacts"."email" is ?"#));
+        assert_eq!(ids(query.all().await?), vec![1]);
+
+        let query = db
+            .select(())
+            .from(contacts)
+            .where_(is_not(contacts.email, "a@b.c"))
+            .order(vec![asc(contacts.id)]);
+        assert!(query
+            .sql::<Contact>()
+            .contains(r#"where "contacts"."email" is not ?"#));
+        assert_eq!(ids(query.all().await?), vec![2, 3]);
+
+        let rows: Vec<Contact> = db
+            .select(())
+            .from(contacts)
+            .where_(is_not(contacts.email, Value::Null))
+            .order(vec![asc(contacts.id)])
+            .all()
+            .await?;
+        assert_eq!(ids(rows), vec![1, 3]);
+
+        Ok(())
+    }
+
+    // how many statements are prepared on the connection, cached ones stay prepared
+    async fn live_statements(db: &ryzz::Database) -> Result<usize, ryzz::Error> {
+        Ok(db
+            .connection
+            .call(|conn| {
+                let mut count = 0;
+                let mut stmt = std::ptr::null_mut();
+                loop {
+                    // SAFETY: walking the connection's statement list without touching the statements
+                    stmt = unsafe { rusqlite::ffi::sqlite3_next_stmt(conn.handle(), stmt) };
+                    if stmt.is_null() {
+                        return Ok(count);
+                    }
+                    count += 1;
+                }
+            })
+            .await?)
+    }
+
+    #[tokio::test]
+    async fn no_cache_works() -> Result<(), ryzz::Error> {
+        use ryzz::*;
+
+        #[table("tags")]
+        struct Tag {
+            #[ryzz(pk)]
+            id: i64,
+            name: String,
+        }
+
+        let db = Database::new(":memory:").await?;
+        let tags = Tag::table(&db).await?;
+
+        let rows = db
+            .insert(tags)
+            .values(Tag {
+                id: 1,
+                name: "rust".into(),
+            })?
+            .no_cache()
+            .rows_affected()
+            .await?;
+        assert_eq!(rows, 1);
+        db.insert(tags)
+            .values(Tag {
+                id: 2,
+                name: "sqlite".into(),
+            })?
+            .rows_affected()
+            .await?;
+
+        let ids: Vec<i64> = (1..=500).collect();
+        let rows: Vec<Tag> = db
+            .select(())
+            .from(tags)
+            .where_(in_(tags.id, ids))
+            .order(vec![asc(tags.id)])
+            .no_cache()
+            .all()
+            .await?;
+        assert_eq!(
+            rows.iter().map(|row| row.name.as_str()).collect::<Vec<_>>(),
+            vec!["rust", "sqlite"]
+        );
+
+        // only `cached` queries stay prepared
+        let db = Database::new(":memory:").await?;
+        let tags = Tag::table(&db).await?;
+        db.connection
+            .call(|conn| {
+                conn.flush_prepared_statement_cache();
+                Ok(())
+            })
+            .await?;
+        db.select(()).from(tags).all::<Tag>().await?;
+        db.execute("create table other (id integer)").await?;
+        assert_eq!(live_statements(&db).await?, 0);
+        db.select(()).from(tags).cached().all::<Tag>().await?;
+        assert_eq!(live_statements(&db).await?, 1);
+        db.select(())
+            .from(tags)
+            .cached()
+            .no_cache()
+            .all::<Tag>()
+            .await?;
+        assert_eq!(live_statements(&db).await?, 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn unbindable_value_errors() {
+        use rusqlite::{blob::ZeroBlob, types::ToSqlOutput, ToSql};
+
+        struct Unbindable;
+
+        impl ToSql for Unbindable {
+            fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+                Err(rusqlite::Error::ToSqlConversionFailure("unbindable".into()))
+            }
+        }
+
+        assert_eq!(
+            super::to_value(&Unbindable),
+            Err(ryzz::Error::ToSql("unbindable".into()))
+        );
+        assert!(matches!(
+            super::to_value(&ZeroBlob(4)),
+            Err(ryzz::Error::ToSql(_))
+        ));
+        assert_eq!(super::to_value(&1i64), Ok(ryzz::Value::Integer(1)));
+    }
+
+    #[tokio::test]
+    async fn begin_works() -> Result<(), ryzz::Error> {
+        use ryzz::*;
+
+        #[table("ledger")]
+        struct Entry {
+            #[ryzz(pk)]
+            id: i64,
+            amount: i64,
+        }
+
+        let db = Database::new(":memory:").await?;
+        let ledger = Entry::table(&db).await?;
+        let count = || async {
+            db.select(())
+                .from(ledger)
+                .all::<Entry>()
+                .await
+                .map(|rows| rows.len())
+        };
+
+        let tx = db.begin().await?;
+        tx.insert(ledger)
+            .values(Entry { id: 1, amount: 10 })?
+            .rows_affected()
+            .await?;
+        let rows: Vec<Entry> = tx.select(()).from(ledger).all().await?;
+        assert_eq!(rows.len(), 1);
+        tx.rollback().await?;
+        assert_eq!(count().await?, 0);
+
+        {
+            let tx = db.begin().await?;
+            tx.insert(ledger)
+                .values(Entry { id: 2, amount: 20 })?
+                .rows_affected()
+                .await?;
+        }
+        assert_eq!(count().await?, 0);
+
+        let tx = db.begin().await?;
+        tx.insert(ledger)
+            .values(Entry { id: 3, amount: 30 })?
+            .rows_affected()
+            .await?;
+        tx.update(ledger)
+            .set(Entry { id: 3, amount: 35 })?
+            .where_(eq(ledger.id, 3))
+            .rows_affected()
+            .await?;
+        tx.commit().await?;
+        let rows: Vec<Entry> = db.select(()).from(ledger).all().await?;
+        assert_eq!(
+            rows.iter()
+                .map(|row| (row.id, row.amount))
+                .collect::<Vec<_>>(),
+            vec![(3, 35)]
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn transaction_builders_work() -> Result<(), ryzz::Error> {
+        use ryzz::*;
+
+        #[table("stock")]
+        struct Item {
+            #[ryzz(pk)]
+            id: i64,
+            qty: i64,
+        }
+
+        let db = Database::new(":memory:").await?;
+        let stock = Item::table(&db).await?;
+
+        let result: Result<(), Error> = db
+            .transaction(|tx| async move {
+                tx.insert(stock)
+                    .values(Item { id: 1, qty: 5 })?
+                    .rows_affected()
+                    .await?;
+                Err(Error::RowNotFound)
+            })
+            .await;
+        assert_eq!(result, Err(Error::RowNotFound));
+        let rows: Vec<Item> = db.select(()).from(stock).all().await?;
+        assert!(rows.is_empty());
+
+        let qty = db
+            .transaction(|tx| async move {
+                tx.insert(stock)
+                    .values(Item { id: 1, qty: 5 })?
+                    .rows_affected()
+                    .await?;
+                tx.update(stock)
+                    .set(Item { id: 1, qty: 4 })?
+                    .where_(eq(stock.id, 1))
+                    .rows_affected()
+                    .await?;
+                let item: Item = tx
+                    .select(())
+                    .from(stock)
+                    .where_(eq(stock.id, 1))
+                    .first()
+                    .await?;
+                Ok(item.qty)
+            })
+            .await?;
+        assert_eq!(qty, 4);
+        let rows: Vec<Item> = db.select(()).from(stock).all().await?;
+        assert_eq!(rows.len(), 1);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn newtype_fields_work() -> Result<(), ryzz::Error> {
+        use ryzz::*;
+        use serde::{Deserialize, Serialize};
+
+        #[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+        struct UserId(i64);
+
+        #[table("users")]
+        struct User {
+            #[ryzz(pk, newtype = "i64")]
+            id: UserId,
+            #[ryzz(newtype = "i64")]
+            invited_by: Option<UserId>,
+            name: String,
+        }
+
+        #[row]
+        struct NewUser {
+            id: UserId,
+            name: String,
+        }
+
+        let db = Database::new(":memory:").await?;
+        let users = User::table(&db).await?;
+        assert_eq!(
+            users.create_table_sql(),
+            r#"create table if not exists "users" ("id" Integer not null primary key,"invited_by" Integer,"name" Text not null);"#
+        );
+
+        let query = db.insert(users).values(User {
+            id: UserId(7),
+            invited_by: None,
+            name: "swlkr".into(),
+        })?;
+        assert_eq!(
+            query.params(),
+            vec![Value::Integer(7), Value::Null, Value::Text("swlkr".into())]
+        );
+        query.rows_affected().await?;
+        db.insert(users)
+            .values(NewUser {
+                id: UserId(8),
+                name: "friend".into(),
+            })?
+            .rows_affected()
+            .await?;
+        db.update(users)
+            .set(User {
+                id: UserId(8),
+                invited_by: Some(UserId(7)),
+                name: "friend".into(),
+            })?
+            .where_(eq(users.id, 8))
+            .rows_affected()
+            .await?;
+
+        let row: User = db
+            .select(())
+            .from(users)
+            .where_(eq(users.invited_by, 7))
+            .first()
+            .await?;
+        assert_eq!(row.id, UserId(8));
+        assert_eq!(row.invited_by, Some(UserId(7)));
+
+        let row: NewUser = db
+            .select(())
+            .from(users)
+            .where_(eq(users.id, 7))
+            .first()
+            .await?;
+        assert_eq!(row.id, UserId(7));
+        assert_eq!(row.name, "swlkr");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn delete_with_order_and_limit_works() -> Result<(), ryzz::Error> {
+        use ryzz::*;
+
+        #[table("events")]
+        struct Event {
+            #[ryzz(pk)]
+            id: i64,
+            created_at: i64,
+        }
+
+        let db = Database::new(":memory:").await?;
+        let events = Event::table(&db).await?;
+
+        for (id, created_at) in [(1, 30), (2, 10), (3, 20), (4, 40)] {
+            db.insert(events)
+                .values(Event { id, created_at })?
+                .rows_affected()
+                .await?;
+        }
+
+        let query = db
+            .delete_from(events)
+            .where_(gt(events.created_at, 0))
+            .order(vec![asc(events.created_at)])
+            .limit(2)
+            .offset(0);
+        assert_eq!(
+            query.sql::<usize>(),
+            r#"delete from "events" where "events"."created_at" > ? order by "events"."created_at" asc limit 2 offset 0"#
+        );
+
+        // the bundled sqlite isn't compiled with SQLITE_ENABLE_UPDATE_DELETE_LIMIT
+        assert_eq!(db.options.update_delete_limit.get(), None);
+        assert!(matches!(
+            query.rows_affected().await,
+            Err(Error::Sql(msg)) if msg.contains("SQLITE_ENABLE_UPDATE_DELETE_LIMIT")
+        ));
+        assert_eq!(db.options.update_delete_limit.get(), Some(&false));
+        assert!(db
+            .update(events)
+            .set(Event {
+                id: 1,
+                created_at: 50
+            })?
+            .limit(1)
+            .rows_affected()
+            .await
+            .is_err());
+        let rows: Vec<Event> = db.select(()).from(events).all().await?;
+        assert_eq!(rows.len(), 4);
+
+        let rows: Vec<Event> = db
+            .select(())
+            .from(events)
+            .order(vec![asc(events.created_at)])
+            .limit(2)
+            .offset(1)
+            .all()
+            .await?;
+        assert_eq!(
+            rows.iter().map(|row| row.id).collect::<Vec<_>>(),
+            vec![3, 1]
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn sqlite_error_code_works() -> Result<(), ryzz::Error> {
+        use ryzz::*;
+
+        #[table("handles")]
+        struct Handle {
+            #[ryzz(pk)]
+            id: i64,
+            #[ryzz(unique)]
+            name: String,
+        }
+
+        let db = Database::new(":memory:").await?;
+        let handles = Handle::table(&db).await?;
+
+        db.insert(handles)
+            .values(Handle {
+                id: 1,
+                name: "swlkr".into(),
+            })?
+            .rows_affected()
+            .await?;
+        let result = db
+            .insert(handles)
+            .values(Handle {
+                id: 2,
+                name: "swlkr".into(),
+            })?
+            .rows_affected()
+            .await;
+
+        match result {
+            Err(Error::Sqlite { code, message }) => {
+                assert_eq!(code, rusqlite::ffi::SQLITE_CONSTRAINT_UNIQUE);
+                assert!(message.contains("UNIQUE constraint failed: handles.name"));
+            }
+            result => panic!("expected a sqlite error, got {:?}", result),
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn keyword_columns_work() -> Result<(), ryzz::Error> {
+        use ryzz::*;
+
+        #[table("lines")]
+        struct Line {
+            #[ryzz(pk)]
+            id: i64,
+            order: i64,
+            #[ryzz(name = "group")]
+            group_name: Option<String>,
+        }
+
+        let db = Database::new(":memory:").await?;
+        let lines = Line::table(&db).await?;
+        assert_eq!(
+            lines.create_table_sql(),
+            r#"create table if not exists "lines" ("id" Integer not null primary key,"order" Integer not null,"group" Text);"#
+        );
+        assert_eq!(
+            lines.add_column_sql("order"),
+            r#"alter table "lines" add column "order" Integer not null;"#
+        );
+        assert_eq!(lines.column("group_name"), Some(r#""lines"."group""#));
+
+        for (id, order) in [(1, 2), (2, 1)] {
+            let query = db.insert(lines).values(Line {
+                id,
+                order,
+                group_name: None,
+            })?;
+            assert!(query.sql::<usize>().contains(r#"("id","order","group")"#));
+            query.rows_affected().await?;
+        }
+
+        let line: Line = db
+            .update(lines)
+            .set(Line {
+                id: 1,
+                order: 3,
+                group_name: Some("a".into()),
+            })?
+            .where_(eq(lines.order, 2))
+            .returning()
+            .await?;
+        assert_eq!(line.order, 3);
+        assert_eq!(line.group_name.as_deref(), Some("a"));
+
+        let query = db.select(()).from(lines).order(vec![desc(lines.order)]);
+        assert!(query.sql::<Line>().contains(r#"'order', "lines"."order""#));
+        let rows: Vec<Line> = query.all().await?;
+        assert_eq!(
+            rows.iter().map(|row| row.id).collect::<Vec<_>>(),
+            vec![1, 2]
+        );
+
+        #[row]
+        struct Order {
+            order: i64,
+        }
+        let rows: Vec<Order> = db
+            .select((lines.order,))
+            .from(lines)
+            .where_(eq(lines.group_name, "a"))
+            .all()
+            .await?;
+        assert_eq!(
+            rows.iter().map(|row| row.order).collect::<Vec<_>>(),
+            vec![3]
+        );
+
+        let line: Line = db
+            .upsert(
+                lines,
+                Line {
+                    id: 2,
+                    order: 5,
+                    group_name: None,
+                },
+                lines.id,
+            )?
+            .returning()
+            .await?;
+        assert_eq!(line.order, 5);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn keyword_table_names_work() -> Result<(), ryzz::Error> {
+        use ryzz::*;
+
+        #[table("group")]
+        struct Group {
+            #[ryzz(pk)]
+            id: i64,
+            name: String,
+        }
+
+        #[table("group")]
+        struct GroupV2 {
+            #[ryzz(pk)]
+            id: i64,
+            name: String,
+            size: Option<i64>,
+        }
+
+        let db = Database::new(":memory:").await?;
+        let groups = Group::table(&db).await?;
+        assert_eq!(groups.table_name(), r#"group"#);
+        assert_eq!(
+            groups.create_table_sql(),
+            r#"create table if not exists "group" ("id" Integer not null primary key,"name" Text not null);"#
+        );
+        assert_eq!(groups.column("name"), Some(r#""group"."name""#));
+
+        db.insert(groups)
+            .values(Group {
+                id: 1,
+                name: "admins".into(),
+            })?
+            .rows_affected()
+            .await?;
+
+        let groups = GroupV2::table(&db).await?;
+        assert_eq!(
+            groups.add_column_sql("size"),
+            r#"alter table "group" add column "size" Integer;"#
+        );
+        assert!(db.column_exists("group", "size").await?);
+
+        let group: GroupV2 = db
+            .update(groups)
+            .set(GroupV2 {
+                id: 1,
+                name: "admins".into(),
+                size: Some(3),
+            })?
+            .where_(eq(groups.id, 1))
+            .returning()
+            .await?;
+        assert_eq!(group.size, Some(3));
+
+        let rows: Vec<GroupV2> = db
+            .select(())
+            .from(groups)
+            .where_(eq(groups.name, "admins"))
+            .all()
+            .await?;
+        assert_eq!(rows.len(), 1);
+
+        assert_eq!(db.truncate(groups).await?, 1);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn index_sql_is_quoted() -> Result<(), ryzz::Error> {
+        use ryzz::*;
+
+        #[table("select")]
+        struct Pick {
+            #[ryzz(pk)]
+            id: i64,
+            order: i64,
+            label: String,
+        }
+
+        let db = Database::new(":memory:").await?;
+        let picks = Pick::table(&db).await?;
+
+        let ix = index("select-order ix")
+            .unique()
+            .on(picks, (picks.order, picks.label));
+        assert_eq!(
+            ix.to_create_sql(),
+            r#"create unique index if not exists "select-order ix" on "select" ("order","label");"#
+        );
+        assert_eq!(
+            ix.to_drop_sql(),
+            r#"drop index if exists "select-order ix";"#
+        );
+
+        let index_count = || async {
+            db.connection
+                .call(|conn| {
+                    conn.query_row(
+                        "select count(*) from sqlite_master where type = 'index' and name = 'select-order ix'",
+                        [],
+                        |row| row.get::<_, i64>(0),
+                    )
+                })
+                .await
+        };
+        db.create(&ix).await?;
+        assert_eq!(index_count().await?, 1);
+        db.drop(&ix).await?;
+        assert_eq!(index_count().await?, 0);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn index_names_work() -> Result<(), ryzz::Error> {
+        use ryzz::*;
+
+        #[table("subscriptions")]
+        struct Subscription {
+            #[ryzz(pk)]
+            id: i64,
+            account_identifier: String,
+            notification_preference: String,
+            billing_cycle_anchor: i64,
+        }
+
+        let db = Database::new(":memory:").await?;
+        let subs = Subscription::table(&db).await?;
+
+        let ix = index("subs_account_ix").on(subs, subs.account_identifier);
+        assert_eq!(ix.name(), "subs_account_ix");
+        assert_eq!(
+            create_index_named_sql("subs_account_ix", true, subs, subs.account_identifier),
+            r#"create unique index if not exists "subs_account_ix" on "subscriptions" ("account_identifier");"#
+        );
+        assert_eq!(
+            create_index_named_sql("subs_account_ix", false, subs, subs.account_identifier),
+            ix.to_create_sql()
+        );
+
+        let ix = index_on(subs, subs.account_identifier).unique();
+        assert_eq!(ix.name(), "subscriptions_account_identifier_ix");
+        assert_eq!(
+            ix.to_create_sql(),
+            r#"create unique index if not exists "subscriptions_account_identifier_ix" on "subscriptions" ("account_identifier");"#
+        );
+
+        let long = index_on(
+            subs,
+            (
+                subs.account_identifier,
+                subs.notification_preference,
+                subs.billing_cycle_anchor,
+            ),
+        );
+        let name = long.name();
+        assert_eq!(name.len(), 63);
+        assert!(name.starts_with("subscriptions_account_identifier_notification_"));
+        assert_eq!(name, long.name());
+        assert_ne!(
+            name,
+            index_on(
+                subs,
+                (subs.account_identifier, subs.notification_preference)
+            )
+            .name()
+        );
+
+        db.create(&long).await?;
+        db.drop(&long).await?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn partial_index_works() -> Result<(), ryzz::Error> {
+        use ryzz::*;
+
+        #[table("members")]
+        struct Member {
+            #[ryzz(pk)]
+            id: i64,
+            email: String,
+            active: i64,
+            role: String,
+        }
+
+        let db = Database::new(":memory:").await?;
+        let members = Member::table(&db).await?;
+
+        let ix = index_on(members, members.email)
+            .unique()
+            .where_(and(eq(members.active, 1), ne(members.role, "it's")))?;
+        assert_eq!(
+            ix.to_create_sql(),
+            r#"create unique index if not exists "members_email_ix" on "members" ("email") where ("members"."active" = 1 and "members"."role" != 'it''s');"#
+        );
+
+        // a ? in a quoted identifier isn't a placeholder
+        let quoted = index_on(members, members.email).where_(Sql {
+            clause: r#""members"."role?" = ? and "members"."a""?" = ?"#.into(),
+            params: vec![Value::Integer(1), Value::Text("b".into())],
+        })?;
+        assert!(quoted
+            .to_create_sql()
+            .ends_with(r#"where "members"."role?" = 1 and "members"."a""?" = 'b';"#));
+        assert!(index_on(members, members.email)
+            .where_(eq(members.active, f64::NAN))
+            .is_err());
+        db.create(&ix).await?;
+
+        for (id, active) in [(1, 0), (2, 0), (3, 1)] {
+            db.insert(members)
+                .values(Member {
+                    id,
+                    email: "a@b.c".into(),
+                    active,
+                    role: "admin".into(),
+                })?
+                .rows_affected()
+                .await?;
+        }
+        let duplicate = db
+            .insert(members)
+            .values(Member {
+                id: 4,
+                email: "a@b.c".into(),
+                active: 1,
+                role: "admin".into(),
+            })?
+            .rows_affected()
+            .await;
+        assert!(matches!(duplicate, Err(Error::Sqlite { .. })));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn expression_index_works() -> Result<(), ryzz::Error> {
+        use ryzz::*;
+
+        #[table("people")]
+        struct Person {
+            #[ryzz(pk)]
+            id: i64,
+            email: String,
+        }
+
+        let db = Database::new(":memory:").await?;
+        let people = Person::table(&db).await?;
+
+        let ix = index_on(people, ()).unique().expr("lower(email)");
+        assert_eq!(ix.name(), "people_lower_email_ix");
+        assert_eq!(
+            ix.to_create_sql(),
+            r#"create unique index if not exists "people_lower_email_ix" on "people" (lower(email));"#
+        );
+        db.create(&ix).await?;
+
+        db.insert(people)
+            .values(Person {
+                id: 1,
+                email: "A@B.C".into(),
+            })?
+            .rows_affected()
+            .await?;
+        let duplicate = db
+            .insert(people)
+            .values(Person {
+                id: 2,
+                email: "a@b.c".into(),
+            })?
+            .rows_affected()
+            .await;
+        assert!(matches!(duplicate, Err(Error::Sqlite { .. })));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn find_works() -> Result<(), ryzz::Error> {
+        use ryzz::*;
+
+        #[table("notes")]
+        struct Note {
+            #[ryzz(pk)]
+            id: i64,
+            body: String,
+        }
+
+        let db = Database::new(":memory:").await?;
+        let notes = Note::table(&db).await?;
+
+        for (id, body) in [(1, "a"), (2, "b")] {
+            db.insert(notes)
+                .values(Note {
+                    id,
+                    body: body.into(),
+                })?
+                .rows_affected()
+                .await?;
+        }
+
+        let query = db.find::<Note>();
+        assert_eq!(query.sql::<Note>(), db.select(()).from(notes).sql::<Note>());
+        let rows: Vec<Note> = query.all().await?;
+        assert_eq!(rows.len(), 2);
+
+        let note: Note = db.find::<Note>().where_(eq(notes.id, 2)).first().await?;
+        assert_eq!(note.body, "b");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn many_works() -> Result<(), ryzz::Error> {
+        use ryzz::*;
+
+        #[table("authors")]
+        struct Author {
+            #[ryzz(pk, many = "Book(author_id)")]
+            id: i64,
+            name: String,
+        }
+
+        #[table("books")]
+        struct Book {
+            #[ryzz(pk)]
+            id: i64,
+            title: String,
+            #[ryzz(fk = "authors(id)")]
+            author_id: i64,
+        }
+
+        let db = Database::new(":memory:").await?;
+        let authors = Author::table(&db).await?;
+        let books = Book::table(&db).await?;
+
+        for id in [1, 2] {
+            db.insert(authors)
+                .values(Author {
+                    id,
+                    name: format!("author {}", id),
+                })?
+                .rows_affected()
+                .await?;
+        }
+        for (id, author_id) in [(1, 1), (2, 2), (3, 1)] {
+            db.insert(books)
+                .values(Book {
+                    id,
+                    title: format!("book {}", id),
+                    author_id,
+                })?
+                .rows_affected()
+                .await?;
+        }
+
+        let author: Author = db
+            .find::<Author>()
+            .where_(eq(authors.id, 1))
+            .first()
+            .await?;
+        let rows: Vec<Book> = db
+            .many::<Book>(&author)
+            .order(vec![asc(books.id)])
+            .all()
+            .await?;
+        assert_eq!(
+            rows.iter().map(|row| row.id).collect::<Vec<_>>(),
+            vec![1, 3]
+        );
+        assert!(db
+            .many::<Book>(&author)
+            .sql::<Book>()
+            .ends_with(r#"where "books"."author_id" = ?"#));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn table_foreign_key_works() -> Result<(), ryzz::Error> {
+        use ryzz::*;
+
+        #[table("shelves")]
+        struct Shelf {
+            #[ryzz(pk)]
+            id: i64,
+        }
+
+        #[table("slots")]
+        #[ryzz(from = "shelf_id", to = "shelves(id)")]
+        struct Slot {
+            #[ryzz(pk)]
+            id: i64,
+            shelf_id: i64,
+        }
+
+        let db = Database::new(":memory:").await?;
+        let shelves = Shelf::table(&db).await?;
+        let slots = Slot::table(&db).await?;
+        assert_eq!(
+            slots.create_table_sql(),
+            r#"create table if not exists "slots" ("id" Integer not null primary key,"shelf_id" Integer not null,foreign key (shelf_id) references shelves(id));"#
+        );
+
+        db.insert(shelves)
+            .values(Shelf { id: 1 })?
+            .rows_affected()
+            .await?;
+        db.insert(slots)
+            .values(Slot { id: 1, shelf_id: 1 })?
+            .rows_affected()
+            .await?;
+        let orphan = db
+            .insert(slots)
+            .values(Slot { id: 2, shelf_id: 2 })?
+            .rows_affected()
+            .await;
+        assert!(matches!(
+            orphan,
+            Err(Error::Sqlite { code, .. }) if code == rusqlite::ffi::SQLITE_CONSTRAINT_FOREIGNKEY
+        ));
+
+        Ok(())
+    }
+
+    #[cfg(feature = "load_extension")]
+    #[tokio::test]
+    async fn enable_load_extension_works() -> Result<(), ryzz::Error> {
+        use ryzz::*;
+
+        let db = Database::new(":memory:").await?;
+        let load_sql = || async {
+            db.connection
+                .call(|conn| {
+                    conn.query_row(
+                        "select load_extension('ryzz-missing-extension')",
+                        [],
+                        |_| Ok(()),
+                    )
+                })
+                .await
+        };
+
+        let err = load_sql().await.unwrap_err().to_string();
+        assert!(err.contains("not authorized"), "{}", err);
+
+        {
+            let guard = unsafe { db.enable_load_extension() }.await?;
+            let err = unsafe { guard.load("ryzz-missing-extension", None) }
+                .await
+                .unwrap_err()
+                .to_string();
+            assert!(!err.contains("not authorized"), "{}", err);
+            let err = load_sql().await.unwrap_err().to_string();
+            assert!(!err.contains("not authorized"), "{}", err);
+        }
+
+        let err = load_sql().await.unwrap_err().to_string();
+        assert!(err.contains("not authorized"), "{}", err);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn ping_works() -> Result<(), ryzz::Error> {
+        use ryzz::*;
+
+        let db = Database::new(":memory:").await?;
+        db.ping().await?;
+
+        db.connection.clone().close().await?;
+        assert_eq!(db.ping().await, Err(Error::ConnectionClosed));
+
+        Ok(())
+    }
+
+    #[test]
+    fn always_and_never_simplify() {
+        use ryzz::*;
+
+        let p = || eq(Text("posts.title"), "a");
+
+        assert_eq!(and(always(), p()).parts(), p().parts());
+        assert_eq!(and(p(), always()).parts(), p().parts());
+        assert_eq!(and(never(), p()).parts(), never().parts());
+        assert_eq!(and(p(), never()).parts(), ("1=0", &[][..]));
+        assert_eq!(or(never(), p()).parts(), p().parts());
+        assert_eq!(or(p(), never()).parts(), p().parts());
+        assert_eq!(or(always(), p()).parts(), ("1=1", &[][..]));
+        assert_eq!(or(p(), always()).parts(), always().parts());
+        assert_eq!(and(always(), always()).parts(), always().parts());
+        assert_eq!(or(never(), never()).parts(), never().parts());
+        assert_eq!(
+            and(p(), or(never(), p())).parts(),
+            (
+                "(posts.title = ? and posts.title = ?)",
+                &[Value::Text("a".into()), Value::Text("a".into())][..]
+            )
+        );
+    }
+
+    #[test]
+    fn is_bindable_works() {
+        use ryzz::*;
+
+        assert!(1i64.is_bindable());
+        assert!("a".is_bindable());
+        assert!(Value::Text("a".into()).is_bindable());
+        assert!(!Value::Null.is_bindable());
+        assert!(!Null(Text("posts.title")).is_bindable());
+        assert!(!Text("posts.title").is_bindable());
+
+        // a null compares with `is null` and adds no param, a bindable value adds one
+        let sql = eq(Text("posts.title"), Value::Null);
+        assert_eq!(sql.parts(), ("posts.title is null", &[][..]));
+        let sql = eq(Text("posts.title"), Value::Integer(1));
+        assert_eq!(sql.parts(), ("posts.title = ?", &[Value::Integer(1)][..]));
+    }
+
+    #[tokio::test]
+    async fn open_works() -> Result<(), ryzz::Error> {
+        use ryzz::*;
+
+        let db = Database::open(":memory:").await?;
+        db.ping().await?;
+        assert_eq!(db.pragma_query::<i64>("foreign_keys").await?, 1);
+
+        let db = Database::open_with(Connection::new(":memory:").foreign_keys(false)).await?;
+        assert_eq!(db.pragma_query::<i64>("foreign_keys").await?, 0);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn conflicting_config_errors() -> Result<(), ryzz::Error> {
+        use ryzz::*;
+
+        let path = "conflicting_config_errors.sqlite3";
+        let result = Database::with(Connection::default(path).read_only(true)).await;
+        assert!(matches!(result, Err(Error::Config(_))));
+        assert!(!std::path::Path::new(path).exists());
+
+        let db = Database::new(path).await?;
+        db.execute_batch("create table t (id integer);").await?;
+        drop(db);
+        let db = Database::with(Connection::default(path).read_only(true)).await;
+        assert!(!matches!(db, Err(Error::Config(_))));
+        drop(db);
+
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = std::fs::remove_file(format!("{}{}", path, suffix));
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn query_kind_works() -> Result<(), ryzz::Error> {
+        use ryzz::*;
+
+        #[table("widgets")]
+        struct Widget {
+            #[ryzz(pk)]
+            id: i64,
+        }
+
+        let db = Database::new(":memory:").await?;
+        let widgets = Widget::table(&db).await?;
+
+        assert_eq!(db.select(()).from(widgets).kind(), QueryKind::Select);
+        assert_eq!(db.find::<Widget>().kind(), QueryKind::Select);
+        assert_eq!(db.insert(widgets).kind(), QueryKind::Insert);
+        assert_eq!(
+            db.insert_or(widgets, OnConflict::Ignore).kind(),
+            QueryKind::Insert
+        );
+        assert_eq!(
+            db.upsert(widgets, Widget { id: 1 }, widgets.id)?.kind(),
+            QueryKind::Insert
+        );
+        assert_eq!(
+            db.insert_select(widgets, (), db.select(()).from(widgets))
+                .kind(),
+            QueryKind::Insert
+        );
+        assert_eq!(db.update(widgets).kind(), QueryKind::Update);
+        assert_eq!(db.delete_from(widgets).kind(), QueryKind::Delete);
+        assert_eq!(
+            db.delete_where_in(widgets, widgets.id, vec![1]).kind(),
+            QueryKind::Delete
+        );
+        assert_eq!(Query::new(&db.connection).kind(), QueryKind::Other);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn embed_migrations_works() -> Result<(), ryzz::Error> {
+        use ryzz::*;
+
+        let migrations = embed_migrations!("tests/migrations");
+        assert_eq!(
+            migrations.iter().map(|m| m.name).collect::<Vec<_>>(),
+            vec!["001_create_accounts.sql", "002_add_email.sql"]
+        );
+
+        let db = Database::new(":memory:").await?;
+        assert_eq!(db.run_migrations(&migrations).await?, 2);
+        assert_eq!(db.run_migrations(&migrations).await?, 0);
+        assert!(db.column_exists("accounts", "email").await?);
 
-        self
-    }
+        #[row]
+        struct Email {
+            email: String,
+        }
+        let rows: Vec<Email> = db
+            .query(sql!("select json_object('email', email) from accounts"))
+            .await?;
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].email, "a@b.c");
 
-    pub fn on(mut self, table: impl Table, columns: impl Select) -> Self {
-        self.columns = columns
-            .columns()
-            .into_iter()
-            .map(unqualify)
-            .collect::<Vec<_>>()
-            .join(",");
-        self.table = table.table_name();
+        #[row]
+        struct Ran {
+            name: String,
+        }
+        let ran: Vec<Ran> = db
+            .query(sql!(
+                "select json_object('name', name) from ryzz_migrations order by name"
+            ))
+            .await?;
+        assert_eq!(
+            ran.iter().map(|m| m.name.as_str()).collect::<Vec<_>>(),
+            vec!["001_create_accounts.sql", "002_add_email.sql"]
+        );
 
-        self
+        // the same sql under two names is two migrations
+        let noops = [
+            SqlMigration {
+                name: "003_noop.sql",
+                sql: "select 1;",
+            },
+            SqlMigration {
+                name: "004_noop.sql",
+                sql: "select 1;",
+            },
+        ];
+        assert_eq!(db.run_migrations(&noops).await?, 2);
+
+        Ok(())
     }
-}
 
-pub fn index<'a>(name: &'a str) -> Index<'a> {
-    Index::new(name)
-}
+    #[tokio::test]
+    async fn serialize_works() -> Result<(), ryzz::Error> {
+        use ryzz::*;
 
-#[derive(Clone, Debug)]
-pub struct Database {
-    pub connection: tokio_rusqlite::Connection,
-}
+        #[table("snapshots")]
+        struct Snapshot {
+            #[ryzz(pk)]
+            id: i64,
+            label: String,
+        }
 
-impl From<tokio_rusqlite::Connection> for Database {
-    fn from(connection: tokio_rusqlite::Connection) -> Self {
-        Self { connection }
-    }
-}
+        let db = Database::new(":memory:").await?;
+        let snapshots = Snapshot::table(&db).await?;
+        for id in 1..=3 {
+            db.insert(snapshots)
+                .values(Snapshot {
+                    id,
+                    label: format!("snapshot {}", id),
+                })?
+                .rows_affected()
+                .await?;
+        }
 
-impl Database {
-    pub async fn new(path: &str) -> Result<Self, Error> {
-        Ok(Self {
-            connection: Connection::default(path).open().await?,
-        })
-    }
+        let bytes = db.serialize().await?;
+        assert!(bytes.starts_with(b"SQLite format 3\0"));
 
-    pub async fn with(connection: Connection) -> Result<Self, Error> {
-        Ok(Self {
-            connection: connection.open().await?,
-        })
-    }
+        let copy = Database::new(":memory:").await?;
+        copy.deserialize(bytes).await?;
+        let rows: Vec<Snapshot> = copy.select(()).from(snapshots).all().await?;
+        assert_eq!(
+            rows.iter()
+                .map(|row| row.label.as_str())
+                .collect::<Vec<_>>(),
+            vec!["snapshot 1", "snapshot 2", "snapshot 3"]
+        );
 
-    pub async fn execute_batch(&self, sql: &str) -> Result<(), Error> {
-        let sql: Arc<str> = sql.into();
-        self.connection
-            .call(move |conn| conn.execute_batch(&sql))
+        copy.insert(snapshots)
+            .values(Snapshot {
+                id: 4,
+                label: "snapshot 4".into(),
+            })?
+            .rows_affected()
             .await?;
+        let rows: Vec<Snapshot> = db.select(()).from(snapshots).all().await?;
+        assert_eq!(rows.len(), 3);
 
         Ok(())
     }
 
-    pub async fn execute(&self, sql: &str) -> Result<usize, Error> {
-        let sql: Arc<str> = sql.into();
-        let affected = self
-            .connection
-            .call(move |conn| conn.execute(&sql, ()))
-            .await?;
+    #[tokio::test]
+    async fn shared_str_fields_work() -> Result<(), ryzz::Error> {
+        use ryzz::*;
+        use std::{borrow::Cow, sync::Arc};
 
-        Ok(affected)
-    }
+        #[table("labels")]
+        struct Label {
+            #[ryzz(pk)]
+            id: i64,
+            name: Arc<str>,
+            color: Cow<'static, str>,
+            note: Option<Arc<str>>,
+        }
 
-    pub async fn query<T: serde::de::DeserializeOwned + Send + 'static>(
-        &self,
-        sql: Sql,
-    ) -> Result<Vec<T>, Error> {
-        ryzz::rows::<T>(&self.connection, sql).await
-    }
+        let db = Database::new(":memory:").await?;
+        let labels = Label::table(&db).await?;
+        assert_eq!(
+            labels.create_table_sql(),
+            r#"create table if not exists "labels" ("id" Integer not null primary key,"name" Text not null,"color" Text not null,"note" Text);"#
+        );
 
-    pub fn select(&self, columns: impl Select) -> Query {
-        Query::new(&self.connection).select(columns)
-    }
+        let name: Arc<str> = "bug".into();
+        let query = db.insert(labels).values(Label {
+            id: 1,
+            name: name.clone(),
+            color: Cow::Borrowed("red"),
+            note: None,
+        })?;
+        assert_eq!(
+            query.params(),
+            vec![
+                Value::Integer(1),
+                Value::Text("bug".into()),
+                Value::Text("red".into()),
+                Value::Null
+            ]
+        );
+        query.rows_affected().await?;
 
-    pub fn insert(&self, table: impl Table) -> Query {
-        Query::new(&self.connection).insert(table)
-    }
+        let label: Label = db
+            .select(())
+            .from(labels)
+            .where_(and(
+                eq(labels.name, name),
+                eq(labels.color, Cow::Borrowed("red")),
+            ))
+            .first()
+            .await?;
+        assert_eq!(&*label.name, "bug");
+        assert_eq!(label.color, "red");
+        assert_eq!(label.note, None);
 
-    pub fn delete_from(&self, table: impl Table) -> Query {
-        Query::new(&self.connection).delete(table)
+        Ok(())
     }
 
-    pub fn update(&self, table: impl Table) -> Query {
-        Query::new(&self.connection).update(table)
-    }
+    #[tokio::test]
+    async fn row_count_works() -> Result<(), ryzz::Error> {
+        use ryzz::*;
 
-    pub async fn create<'a>(&'a self, index: &'a Index<'a>) -> Result<(), Error> {
-        let sql = index.to_create_sql();
-        let rows = self.execute(&sql).await?;
-        if rows > 0 {
-            println!("{}", sql);
+        #[table("tags")]
+        struct Tag {
+            #[ryzz(pk)]
+            id: i64,
+            name: String,
+        }
+
+        let db = Database::new(":memory:").await?;
+        let tags = Tag::table(&db).await?;
+        for (id, name) in [(1, "a"), (2, "b"), (3, "a")] {
+            db.insert(tags)
+                .values(Tag {
+                    id,
+                    name: name.into(),
+                })?
+                .rows_affected()
+                .await?;
         }
+
+        let query = db.select(()).from(tags).where_(eq(tags.name, "a"));
+        let row: RowCount = query.clone_for_count().first().await?;
+        assert_eq!(row, RowCount { count: 2 });
+        assert_eq!(RowCount::column_names(), vec!["count"]);
+
+        let Count(n) = query.clone_for_count().first().await?;
+        assert_eq!(n, 2);
+
         Ok(())
     }
 
-    pub async fn drop<'a>(&'a self, index: &'a Index<'a>) -> Result<(), Error> {
-        let sql = index.to_drop_sql();
-        let rows = self.execute(&sql).await?;
-        if rows > 0 {
-            println!("{}", sql);
+    #[tokio::test]
+    async fn all_dynamic_works() -> Result<(), ryzz::Error> {
+        use ryzz::*;
+
+        #[table("accounts")]
+        struct Account {
+            #[ryzz(pk)]
+            id: i64,
+            name: String,
+            balance: f64,
+            nickname: Option<String>,
         }
+
+        let db = Database::new(":memory:").await?;
+        let accounts = Account::table(&db).await?;
+        db.insert(accounts)
+            .values(Account {
+                id: 1,
+                name: "a".into(),
+                balance: 1.5,
+                nickname: None,
+            })?
+            .rows_affected()
+            .await?;
+
+        let rows = db.select(()).from(accounts).all_dynamic().await?;
+        assert_eq!(rows.len(), 1);
+        assert_eq!(
+            rows[0].keys().collect::<Vec<_>>(),
+            vec!["balance", "id", "name", "nickname"]
+        );
+        assert_eq!(rows[0]["id"], Value::Integer(1));
+        assert_eq!(rows[0]["name"], Value::Text("a".into()));
+        assert_eq!(rows[0]["balance"], Value::Real(1.5));
+        assert_eq!(rows[0]["nickname"], Value::Null);
+
+        let rows = db
+            .select(columns!(accounts.name))
+            .from(accounts)
+            .all_dynamic()
+            .await?;
+        assert_eq!(rows[0].len(), 1);
+        assert_eq!(rows[0]["name"], Value::Text("a".into()));
+
         Ok(())
     }
 
-    pub async fn schema(&self) -> Result<String, Error> {
-        let schema_table = SqliteSchemaTable::new();
-        let rows = self
-            .select(())
-            .from(schema_table)
-            .all::<SqliteSchema>()
-            .await?;
-        Ok(rows
-            .into_iter()
-            .map(|row| row.sql.to_lowercase())
-            .collect::<Vec<_>>()
-            .join("\n"))
+    #[test]
+    fn tagged_value_round_trips() -> Result<(), serde_json::Error> {
+        use ryzz::*;
+
+        for (value, json) in [
+            (Value::Null, r#""Null""#),
+            (Value::Integer(1), r#"{"Integer":1}"#),
+            (Value::Real(1.5), r#"{"Real":1.5}"#),
+            (Value::Text("a".into()), r#"{"Text":"a"}"#),
+            (Value::Blob(vec![]), r#"{"Blob":""}"#),
+            (Value::Blob(b"f".to_vec()), r#"{"Blob":"Zg=="}"#),
+            (Value::Blob(b"fo".to_vec()), r#"{"Blob":"Zm8="}"#),
+            (Value::Blob(b"foo".to_vec()), r#"{"Blob":"Zm9v"}"#),
+            (Value::Blob(vec![0, 255, 128, 7]), r#"{"Blob":"AP+ABw=="}"#),
+        ] {
+            let tagged = TaggedValue(value);
+            assert_eq!(serde_json::to_string(&tagged)?, json);
+            assert_eq!(serde_json::from_str::<TaggedValue>(json)?, tagged);
+        }
+
+        assert!(serde_json::from_str::<TaggedValue>(r#"{"Blob":"Z!=="}"#).is_err());
+
+        Ok(())
     }
-}
 
-#[cfg(test)]
-mod tests {
     #[tokio::test]
-    #[allow(unused)]
-    async fn readme_works() -> Result<(), ryzz::Error> {
+    async fn exists_subquery_works() -> Result<(), ryzz::Error> {
         use ryzz::*;
 
-        #[table("posts")]
-        struct Post {
+        #[table("authors")]
+        struct Author {
             #[ryzz(pk)]
             id: i64,
-            title: Option<String>,
-            body: String,
+            name: String,
         }
 
-        #[table]
-        struct Comment {
+        #[table("books")]
+        struct Book {
             #[ryzz(pk)]
             id: i64,
-            body: String,
-            #[ryzz(fk = "posts(id)")]
-            post_id: i64,
+            author_id: i64,
+            title: String,
         }
 
         let db = Database::new(":memory:").await?;
-        let posts = Post::table(&db).await?;
-        let comments = Comment::table(&db).await?;
+        let authors = Author::table(&db).await?;
+        let books = Book::table(&db).await?;
+        for (id, name) in [(1, "a"), (2, "b")] {
+            db.insert(authors)
+                .values(Author {
+                    id,
+                    name: name.into(),
+                })?
+                .rows_affected()
+                .await?;
+        }
+        db.insert(books)
+            .values(Book {
+                id: 1,
+                author_id: 1,
+                title: "x".into(),
+            })?
+            .rows_affected()
+            .await?;
 
-        let post = Post {
-            id: 1,
-            title: None,
-            body: "".into(),
+        let has_books = |title: &str| {
+            db.select(()).from(books).where_(and(
+                eq(books.author_id, authors.id),
+                eq(books.title, title.to_string()),
+            ))
         };
 
-        // insert into posts (id, body) values (?, ?) returning *
-        let mut post: Post = db.insert(posts).values(post)?.returning().await?;
+        let filter = exists_subquery(has_books("x"));
+        assert_eq!(
+            r#"exists (select 1 from "books" where ("books"."author_id" = "authors"."id" and "books"."title" = ?))"#,
+            filter.clause
+        );
+        assert_eq!(vec![Value::Text("x".into())], filter.params);
 
-        assert_eq!(post.id, 1);
-        assert_eq!(post.body, "");
-        assert_eq!(post.title, None);
+        let rows: Vec<Author> = db.select(()).from(authors).where_(filter).all().await?;
+        assert_eq!(rows.iter().map(|a| a.id).collect::<Vec<_>>(), vec![1]);
 
-        post.body = "post".into();
+        let rows: Vec<Author> = db
+            .select(())
+            .from(authors)
+            .where_(not_exists_subquery(has_books("x")))
+            .all()
+            .await?;
+        assert_eq!(rows.iter().map(|a| a.id).collect::<Vec<_>>(), vec![2]);
 
-        // update posts set body = ?, id = ? where id = ? returning *
-        let post: Post = db
-            .update(posts)
-            .set(post)?
-            .where_(and(eq(posts.id, 1), eq(posts.title, Value::Null)))
-            .returning()
+        let rows: Vec<Author> = db
+            .select(())
+            .from(authors)
+            .where_(exists_subquery(has_books("y")))
+            .all()
             .await?;
+        assert!(rows.is_empty());
 
-        assert_eq!(post.id, 1);
-        assert_eq!(post.body, "post");
+        Ok(())
+    }
 
-        // delete from posts where id = ? returning *
-        let post: Post = db
-            .delete_from(posts)
-            .where_(eq(posts.id, 1))
-            .returning()
-            .await?;
+    #[tokio::test]
+    async fn aggregate_works() -> Result<(), ryzz::Error> {
+        use ryzz::*;
 
-        assert_eq!(post.id, 1);
-        assert_eq!(post.body, "post");
+        #[table("sales")]
+        struct Sale {
+            #[ryzz(pk)]
+            id: i64,
+            region: String,
+            amount: i64,
+        }
 
-        let rows: Vec<Post> = db.select(()).from(posts).all().await?;
+        #[row]
+        struct RegionTotal {
+            region: String,
+            total: i64,
+            sales: i64,
+        }
 
-        assert_eq!(rows.len(), 0);
+        let db = Database::new(":memory:").await?;
+        let sales = Sale::table(&db).await?;
+        for (id, region, amount) in [(1, "east", 10), (2, "west", 5), (3, "east", 7)] {
+            db.insert(sales)
+                .values(Sale {
+                    id,
+                    region: region.into(),
+                    amount,
+                })?
+                .rows_affected()
+                .await?;
+        }
 
-        let inserted: Post = db
-            .insert(posts)
-            .values(Post {
-                title: None,
-                id: 1,
-                body: "".into(),
-            })?
-            .returning()
-            .await?;
+        let query = db
+            .select(())
+            .from(sales)
+            .aggregate(
+                sales.region,
+                vec![
+                    sql!("sum(sales.amount)").as_("total"),
+                    sql!("count(*)").as_("sales"),
+                ],
+            )
+            .order(vec![asc(sales.region)]);
 
-        assert_eq!(rows.len(), 0);
+        assert_eq!(
+            r#"select json_object('region', "sales"."region",'total', sum(sales.amount),'sales', count(*))  from "sales" group by "sales"."region" order by "sales"."region" asc"#,
+            query.sql::<RegionTotal>()
+        );
 
-        let new_comment = Comment {
-            id: 1,
-            body: "".into(),
-            post_id: 1,
+        let rows: Vec<RegionTotal> = query.all().await?;
+        assert_eq!(
+            rows.iter()
+                .map(|r| (r.region.as_str(), r.total, r.sales))
+                .collect::<Vec<_>>(),
+            vec![("east", 17, 2), ("west", 5, 1)]
+        );
+
+        #[row]
+        struct Total {
+            total: i64,
+        }
+
+        let query = db
+            .select(())
+            .from(sales)
+            .aggregate((), vec![sql!("sum(sales.amount)").as_("total")]);
+        assert_eq!(
+            r#"select json_object('total', sum(sales.amount)) from "sales""#,
+            query.sql::<Total>()
+        );
+        let total: Total = query.first().await?;
+        assert_eq!(total.total, 22);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn busy_handler_works() -> Result<(), ryzz::Error> {
+        use ryzz::*;
+        use std::sync::atomic::{AtomicI32, Ordering};
+
+        let calls = Arc::new(AtomicI32::new(0));
+        let give_up_after_three = {
+            let calls = calls.clone();
+            move |count| {
+                calls.fetch_add(1, Ordering::SeqCst);
+                count < 3
+            }
         };
 
-        let comment: Comment = db
-            .insert(comments)
-            .values(new_comment.clone())?
-            .returning()
+        #[table("jobs")]
+        struct Job {
+            #[ryzz(pk)]
+            id: i64,
+        }
+
+        let path = std::env::temp_dir().join(format!("ryzz-busy-{}.sqlite3", std::process::id()));
+        let path = path.to_str().unwrap();
+        let _ = std::fs::remove_file(path);
+
+        let db = Database::new(path).await?;
+        let jobs = Job::table(&db).await?;
+        let other = Database::with(Connection::new(path).busy_handler(give_up_after_three)).await?;
+
+        let tx = db.begin().await?;
+        tx.insert(jobs)
+            .values(Job { id: 1 })?
+            .rows_affected()
             .await?;
 
-        let rows: Vec<Comment> = db.select(()).from(comments).all().await?;
+        let err = other
+            .insert(jobs)
+            .values(Job { id: 2 })?
+            .rows_affected()
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(err, Error::Sqlite { code, .. } if code & 0xff == rusqlite::ffi::SQLITE_BUSY),
+            "{err:?}"
+        );
+        assert_eq!(calls.load(Ordering::SeqCst), 4);
 
-        assert_eq!(rows.len(), 1);
-        let row = &rows[0];
-        assert_eq!(row.id, new_comment.id);
-        assert_eq!(row.body, new_comment.body);
-        assert_eq!(row.post_id, new_comment.post_id);
+        tx.rollback().await?;
+        other
+            .insert(jobs)
+            .values(Job { id: 2 })?
+            .rows_affected()
+            .await?;
 
-        let query = db
-            .select((comments.id, comments.body))
-            .from(comments)
-            .where_(eq(comments.id, 1))
-            .limit(1);
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = std::fs::remove_file(format!("{path}{suffix}"));
+        }
 
-        let rows: Vec<Comment> = query.all().await?;
+        Ok(())
+    }
 
-        assert_eq!(rows.len(), 1);
+    #[tokio::test]
+    async fn gt_all_and_lt_any_work() -> Result<(), ryzz::Error> {
+        use ryzz::*;
 
-        let comment = rows.into_iter().nth(0).unwrap();
-        assert_eq!(comment.id, new_comment.id);
-        assert_eq!(comment.body, new_comment.body);
-        assert_eq!(comment.post_id, 0);
+        #[table("bids")]
+        struct Bid {
+            #[ryzz(pk)]
+            id: i64,
+            lot: String,
+            amount: i64,
+        }
 
-        #[row]
-        struct CommentWithPost {
-            comment: Comment,
-            post: Post,
+        let db = Database::new(":memory:").await?;
+        let bids = Bid::table(&db).await?;
+        for (id, lot, amount) in [(1, "a", 10), (2, "a", 30), (3, "b", 20), (4, "b", 5)] {
+            db.insert(bids)
+                .values(Bid {
+                    id,
+                    lot: lot.into(),
+                    amount,
+                })?
+                .rows_affected()
+                .await?;
         }
 
-        let rows: Vec<CommentWithPost> = db
+        let lot_b = || db.select(bids.amount).from(bids).where_(eq(bids.lot, "b"));
+
+        // the outer table needs an alias, inside the subquery `bids` is the inner one
+        let filter = gt_all(aliased("o", bids.amount), lot_b())?;
+        assert_eq!(
+            r#"not exists (select 1 from "bids" where ("bids"."lot" = ?) and "bids"."amount" >= "o"."amount")"#,
+            filter.clause
+        );
+        assert_eq!(vec![Value::Text("b".into())], filter.params);
+
+        let ids = |rows: Vec<Bid>| rows.into_iter().map(|b| b.id).collect::<Vec<_>>();
+        let rows: Vec<Bid> = db
             .select(())
-            .from(comments)
-            .inner_join(posts, eq(posts.id, comments.post_id))
+            .from_as(bids, "o")
+            .where_(filter)
             .all()
             .await?;
+        assert_eq!(ids(rows), vec![2]);
 
-        assert_eq!(rows[0].comment.id, new_comment.id);
-        assert_eq!(rows[0].comment.body, new_comment.body);
-        assert_eq!(rows[0].comment.post_id, new_comment.post_id);
-        assert_eq!(rows[0].post.id, inserted.id);
-        assert_eq!(rows[0].post.title, inserted.title);
-        assert_eq!(rows[0].post.body, inserted.body);
-
-        let query = db.select(()).from(comments);
-
-        // prepare the query
-        let prepared = query.prep::<Comment>().await?;
-
-        // execute the prepared query later
-        let rows: Vec<Comment> = prepared.all().await?;
+        let rows: Vec<Bid> = db
+            .select(())
+            .from_as(bids, "o")
+            .where_(lt_any(aliased("o", bids.amount), lot_b())?)
+            .all()
+            .await?;
+        assert_eq!(ids(rows), vec![1, 4]);
 
-        assert_eq!(rows[0].id, 1);
-        assert_eq!(rows[0].body, "");
-        assert_eq!(rows[0].post_id, 1);
+        assert!(gt_all(bids.amount, db.select(()).from(bids)).is_err());
 
         Ok(())
     }
 
-    #[allow(unused)]
     #[tokio::test]
-    async fn indexes_works() -> Result<(), ryzz::Error> {
+    async fn typed_comparisons_work() -> Result<(), ryzz::Error> {
         use ryzz::*;
 
-        #[table]
-        struct Link {
+        #[table("accounts")]
+        struct Account {
             #[ryzz(pk)]
             id: i64,
-            url: String,
+            name: String,
+            nickname: Option<String>,
+            balance: f64,
         }
 
         let db = Database::new(":memory:").await?;
-        let links = Link::table(&db).await?;
-
-        let links_url_ix = index("links_url_ix").unique().on(links, links.url);
-
-        db.create(&links_url_ix).await?;
-
-        let rows = db
-            .insert(links)
-            .values(Link {
+        let accounts = Account::table(&db).await?;
+        db.insert(accounts)
+            .values(Account {
                 id: 1,
-                url: "".into(),
+                name: "a".into(),
+                nickname: None,
+                balance: 2.0,
             })?
             .rows_affected()
             .await?;
 
-        assert_eq!(rows, 1);
+        let sql = typed::eq(accounts.id, 1);
+        assert_eq!(r#""accounts"."id" = ?"#, sql.clause);
+        assert_eq!(vec![Value::Integer(1)], sql.params);
+        let sql = typed::ne(accounts.name, "a");
+        assert_eq!(r#""accounts"."name" != ?"#, sql.clause);
+        assert_eq!(vec![Value::Text("a".into())], sql.params);
+        assert_eq!(
+            r#""accounts"."nickname" = "accounts"."name""#,
+            typed::eq(accounts.nickname, accounts.name).clause
+        );
+        assert_eq!(
+            r#""accounts"."nickname" is null"#,
+            typed::eq(accounts.nickname, Value::Null).clause
+        );
+        assert_eq!(
+            vec![Value::Integer(1)],
+            typed::gte(accounts.balance, 1).params
+        );
+        assert_eq!(
+            r#""accounts"."balance" < ?"#,
+            typed::lt(accounts.balance, 2.5).clause
+        );
 
-        let result = db
-            .insert(links)
-            .values(Link {
-                id: 2,
-                url: "".into(),
-            })?
-            .rows_affected()
-            .await;
+        let rows: Vec<Account> = db
+            .select(())
+            .from(accounts)
+            .where_(and(
+                typed::eq(accounts.name, "a"),
+                typed::gt(accounts.balance, 1),
+            ))
+            .all()
+            .await?;
+        assert_eq!(rows.len(), 1);
 
-        assert!(result.is_err());
+        Ok(())
+    }
 
-        db.drop(&links_url_ix).await?;
+    #[tokio::test]
+    async fn execute_script_works() -> Result<(), ryzz::Error> {
+        use ryzz::*;
 
-        let result = db
-            .insert(links)
-            .values(Link {
-                id: 2,
-                url: "".into(),
-            })?
-            .rows_affected()
-            .await;
+        let db = Database::new(":memory:").await?;
+        db.execute("create table notes (id integer primary key, body text not null)")
+            .await?;
 
-        assert!(result.is_ok());
+        let affected = db
+            .execute_script(
+                "insert into notes (body) values ('a; b'), ('c');
+                 update notes set body = body || ';' where id > 0;",
+            )
+            .await?;
+        assert_eq!(affected, vec![2, 2]);
 
-        let rows = db.select(()).from(links).all::<Link>().await?;
+        let affected = db
+            .execute_script("delete from notes where body = 'c;'; ; delete from notes")
+            .await?;
+        assert_eq!(affected, vec![1, 1]);
 
-        assert_eq!(rows.len(), 2);
+        assert!(db.execute_script("select 1;").await.is_err());
+
+        let affected = db
+            .execute_script(
+                "/* a */; insert into notes (body) values ('d'); -- done
+                 create table tags (name text); insert into tags values ('x'); /* end */",
+            )
+            .await?;
+        assert_eq!(affected, vec![1, 0, 1]);
+
+        // a failing statement undoes the ones before it
+        assert!(db
+            .execute_script(
+                "insert into notes (body) values ('e'); insert into missing values (1);"
+            )
+            .await
+            .is_err());
+        let rows: Vec<RowCount> = db
+            .query(sql!("select json_object('count', count(*)) from notes"))
+            .await?;
+        assert_eq!(rows[0].count, 1);
 
         Ok(())
     }
 
-    #[allow(unused)]
     #[tokio::test]
-    async fn migrate_works() -> Result<(), ryzz::Error> {
+    async fn row_number_works() -> Result<(), ryzz::Error> {
         use ryzz::*;
 
-        #[table("links")]
-        struct Link {
+        #[table("scores")]
+        struct Score {
             #[ryzz(pk)]
             id: i64,
-            url: String,
+            team: String,
+            points: i64,
+        }
+
+        #[row]
+        struct Ranked {
+            id: i64,
+            rank: i64,
         }
 
         let db = Database::new(":memory:").await?;
-        let links = Link::table(&db).await?;
-        let schema = db.schema().await?;
+        let scores = Score::table(&db).await?;
+        for (id, team, points) in [(1, "a", 10), (2, "a", 30), (3, "b", 20), (4, "a", 20)] {
+            db.insert(scores)
+                .values(Score {
+                    id,
+                    team: team.into(),
+                    points,
+                })?
+                .rows_affected()
+                .await?;
+        }
 
+        let expr = row_number()
+            .partition_by(scores.team)
+            .order_by(vec![desc(scores.points)])
+            .as_("rank");
         assert_eq!(
-            schema,
-            "create table links (id integer not null primary key,url text not null)"
+            r#"row_number() over (partition by "scores"."team" order by "scores"."points" desc)"#,
+            expr.clause
         );
 
-        let links = Link::table(&db).await?;
-        let schema = db.schema().await?;
-
+        let rows: Vec<Ranked> = db
+            .select(scores.id)
+            .from(scores)
+            .select_expr(expr)
+            .order(vec![asc(scores.id)])
+            .all()
+            .await?;
         assert_eq!(
-            schema,
-            "create table links (id integer not null primary key,url text not null)"
+            rows.iter().map(|r| (r.id, r.rank)).collect::<Vec<_>>(),
+            vec![(1, 3), (2, 1), (3, 1), (4, 2)]
         );
 
-        #[table("links")]
-        struct Link1 {
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn aggregate_window_works() -> Result<(), ryzz::Error> {
+        use ryzz::*;
+
+        #[table("deposits")]
+        struct Deposit {
             #[ryzz(pk)]
             id: i64,
-            url: String,
-            test: Option<String>,
+            account: String,
+            amount: i64,
         }
 
-        let links = Link1::table(&db).await?;
-        let schema = db.schema().await?;
+        #[row]
+        struct Running {
+            id: i64,
+            total: i64,
+            average: f64,
+        }
+
+        let db = Database::new(":memory:").await?;
+        let deposits = Deposit::table(&db).await?;
+        for (id, account, amount) in [(1, "a", 10), (2, "b", 5), (3, "a", 15), (4, "a", 30)] {
+            db.insert(deposits)
+                .values(Deposit {
+                    id,
+                    account: account.into(),
+                    amount,
+                })?
+                .rows_affected()
+                .await?;
+        }
 
+        let total = sum(deposits.amount).over(deposits.account, vec![asc(deposits.id)]);
+        let total = Sql::from(total);
         assert_eq!(
-            schema,
-            "create table links (id integer not null primary key,url text not null, test text)"
+            r#"sum("deposits"."amount") over (partition by "deposits"."account" order by "deposits"."id" asc)"#,
+            total.clause
+        );
+
+        let rows: Vec<Running> = db
+            .select(deposits.id)
+            .from(deposits)
+            .select_expr(total.as_("total"))
+            .select_expr(
+                avg(deposits.amount)
+                    .over((), vec![asc(deposits.id)])
+                    .as_("average"),
+            )
+            .order(vec![asc(deposits.id)])
+            .all()
+            .await?;
+        assert_eq!(
+            rows.iter()
+                .map(|r| (r.id, r.total, r.average))
+                .collect::<Vec<_>>(),
+            vec![(1, 10, 10.0), (2, 5, 7.5), (3, 25, 10.0), (4, 55, 15.0)]
         );
 
         Ok(())
     }
 
-    #[allow(unused)]
     #[tokio::test]
-    async fn null_value_in_where_clause_works() -> Result<(), ryzz::Error> {
+    async fn exclusive_transaction_works() -> Result<(), ryzz::Error> {
         use ryzz::*;
 
-        #[table]
-        struct Chat {
+        #[table("ledger")]
+        struct Ledger {
             #[ryzz(pk)]
             id: i64,
-            msg: Option<String>,
         }
 
-        let db = Database::new(":memory:").await?;
-        let chats = Chat::table(&db).await?;
-
-        let chat = Chat { id: 1, msg: None };
-        let chat2 = Chat {
-            id: 2,
-            msg: Some("".into()),
-        };
-
-        let chat: Chat = db.insert(chats).values(chat)?.returning().await?;
-        let chat2: Chat = db.insert(chats).values(chat2)?.returning().await?;
-
-        let rows: Vec<Chat> = db
-            .select(())
-            .from(chats)
-            .where_(eq(chats.msg, Value::Null))
-            .all()
-            .await?;
+        let path =
+            std::env::temp_dir().join(format!("ryzz-exclusive-{}.sqlite3", std::process::id()));
+        let path = path.to_str().unwrap();
+        let _ = std::fs::remove_file(path);
+
+        let db = Database::with(Connection::new(path)).await?;
+        let ledger = Ledger::table(&db).await?;
+        let other = Database::with(Connection::new(path).pragma("busy_timeout = 0")).await?;
+
+        let is_busy = |err: Error| matches!(err, Error::Sqlite { code, .. } if code & 0xff == rusqlite::ffi::SQLITE_BUSY);
+
+        db.transaction_with(TransactionBehavior::Exclusive, |tx| async move {
+            tx.insert(ledger)
+                .values(Ledger { id: 1 })?
+                .rows_affected()
+                .await?;
+
+            let err = other
+                .insert(ledger)
+                .values(Ledger { id: 2 })?
+                .rows_affected()
+                .await
+                .unwrap_err();
+            assert!(is_busy(err));
+
+            let err = other
+                .select(())
+                .from(ledger)
+                .all::<Ledger>()
+                .await
+                .unwrap_err();
+            assert!(is_busy(err));
+
+            Ok(())
+        })
+        .await?;
 
+        let other = Database::with(Connection::new(path)).await?;
+        let rows: Vec<Ledger> = other.select(()).from(ledger).all().await?;
         assert_eq!(rows.len(), 1);
 
-        let rows: Vec<Chat> = db
-            .select(())
-            .from(chats)
-            .where_(eq(chats.msg, "".to_string()))
-            .all()
-            .await?;
-
-        assert_eq!(rows.len(), 1);
+        for suffix in ["", "-journal"] {
+            let _ = std::fs::remove_file(format!("{path}{suffix}"));
+        }
 
         Ok(())
     }
 
     #[tokio::test]
-    async fn more_complicated_where_clause_works() -> Result<(), ryzz::Error> {
+    async fn method_comparisons_work() -> Result<(), ryzz::Error> {
         use ryzz::*;
 
-        #[table]
-        struct Glyph {
-            image: String,
-            aspect: i64,
+        #[table("accounts")]
+        struct Account {
+            #[ryzz(pk)]
+            id: i64,
+            name: String,
+            nickname: Option<String>,
         }
 
         let db = Database::new(":memory:").await?;
-        let glyphs = Glyph::table(&db).await?;
-
-        let query = db
-            .select(glyphs.image)
-            .from(glyphs)
-            .where_(or(in_(glyphs.aspect, vec![1, 2]), like(glyphs.image, "A%")));
-
-        let sql = query.sql_statement::<Glyph>();
+        let accounts = Account::table(&db).await?;
+        for (id, name) in [(1, "a"), (2, "b"), (3, "c")] {
+            db.insert(accounts)
+                .values(Account {
+                    id,
+                    name: name.into(),
+                    nickname: None,
+                })?
+                .rows_affected()
+                .await?;
+        }
 
+        let sql = accounts.id.eq(1);
+        assert_eq!(r#""accounts"."id" = ?"#, sql.clause);
+        assert_eq!(vec![Value::Integer(1)], sql.params);
+        assert_eq!(r#""accounts"."name" != ?"#, accounts.name.ne("a").clause);
+        assert_eq!(r#""accounts"."id" > ?"#, accounts.id.gt(1).clause);
+        assert_eq!(r#""accounts"."id" < ?"#, accounts.id.lt(1).clause);
+        assert_eq!(r#""accounts"."id" >= ?"#, accounts.id.gte(1).clause);
+        assert_eq!(r#""accounts"."id" <= ?"#, accounts.id.lte(1).clause);
         assert_eq!(
-            "select json_object('image', Glyph.image)  from Glyph where (Glyph.aspect in (?,?) or Glyph.image like ?)",
-            sql.clause
+            r#""accounts"."nickname" is null"#,
+            accounts.nickname.eq(Value::Null).clause
         );
 
-        assert_eq!(
-            vec![
-                Value::Integer(1),
-                Value::Integer(2),
-                Value::Text("A%".into())
-            ],
-            sql.params
-        );
+        let rows: Vec<Account> = db
+            .select(())
+            .from(accounts)
+            .where_(and(accounts.id.gte(2), accounts.name.ne("c")))
+            .all()
+            .await?;
+        assert_eq!(rows.iter().map(|a| a.id).collect::<Vec<_>>(), vec![2]);
 
         Ok(())
     }
 
     #[tokio::test]
-    async fn default_works() -> Result<(), ryzz::Error> {
+    async fn contains_starts_with_and_ends_with_work() -> Result<(), ryzz::Error> {
         use ryzz::*;
 
-        #[table("defaults")]
-        struct Default {
-            #[ryzz(default_ = "'hello'")]
-            im_default: Option<String>,
+        #[table("files")]
+        struct File {
+            #[ryzz(pk)]
+            id: i64,
+            name: String,
         }
 
         let db = Database::new(":memory:").await?;
-        let defaults = Default::table(&db).await?;
+        let files = File::table(&db).await?;
+        for (id, name) in [(1, "100%_done.txt"), (2, "100 done.txt"), (3, "notes.md")] {
+            db.insert(files)
+                .values(File {
+                    id,
+                    name: name.into(),
+                })?
+                .rows_affected()
+                .await?;
+        }
 
+        let sql = contains(files.name, "0%_d");
+        assert_eq!(r#""files"."name" like ? escape '\'"#, sql.clause);
+        assert_eq!(vec![Value::Text(r"%0\%\_d%".into())], sql.params);
         assert_eq!(
-            "create table defaults (im_default text default 'hello')",
-            db.schema().await?
+            vec![Value::Text(r"100\%%".into())],
+            starts_with(files.name, "100%").params
+        );
+        assert_eq!(
+            vec![Value::Text(r"%\\.md".into())],
+            ends_with(files.name, r"\.md").params
         );
 
-        let query = db.insert(defaults).default_values();
-        let sql = query.sql_statement::<Default>();
-
-        assert_eq!(sql.clause, "insert into defaults default values");
-        assert_eq!(sql.params, vec![]);
+        let ids = |rows: Vec<File>| rows.into_iter().map(|f| f.id).collect::<Vec<_>>();
+        let rows: Vec<File> = db
+            .select(())
+            .from(files)
+            .where_(contains(files.name, "0%_d"))
+            .all()
+            .await?;
+        assert_eq!(ids(rows), vec![1]);
 
-        let row: Default = query.returning().await?;
+        let rows: Vec<File> = db
+            .select(())
+            .from(files)
+            .where_(starts_with(files.name, "100"))
+            .all()
+            .await?;
+        assert_eq!(ids(rows), vec![1, 2]);
 
-        assert_eq!(row.im_default, Some("hello".into()));
+        let rows: Vec<File> = db
+            .select(())
+            .from(files)
+            .where_(ends_with(files.name, ".MD"))
+            .all()
+            .await?;
+        assert_eq!(ids(rows), vec![3]);
 
         Ok(())
     }
@@ -0,0 +1 @@
+create table if not exists accounts (id integer primary key, name text not null);
@@ -0,0 +1,2 @@
+alter table accounts add column email text;
+insert into accounts (name, email) values ('swlkr', 'a@b.c');
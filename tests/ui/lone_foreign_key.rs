@@ -0,0 +1,11 @@
+use ryzz::*;
+
+#[table("books")]
+#[ryzz(from = "author_id")]
+struct Book {
+    #[ryzz(pk)]
+    id: i64,
+    author_id: i64,
+}
+
+fn main() {}
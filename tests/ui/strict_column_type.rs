@@ -0,0 +1,11 @@
+use ryzz::*;
+
+#[derive(Table)]
+#[ryzz(table = "events", strict)]
+struct EventTable {
+    #[ryzz(pk)]
+    id: Integer,
+    at: std::time::SystemTime,
+}
+
+fn main() {}
@@ -0,0 +1,14 @@
+use ryzz::*;
+
+#[table("accounts")]
+struct Account {
+    #[ryzz(pk)]
+    id: i64,
+    balance: f64,
+}
+
+fn main() {
+    let accounts = AccountTable::new();
+    typed::eq(accounts.id, "notanumber");
+    accounts.balance.gt("a lot");
+}